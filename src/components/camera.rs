@@ -2,3 +2,61 @@ use bevy::prelude::*;
 
 #[derive(Component)]
 pub struct CameraFocus;
+
+// how strongly a focus target pulls the camera, 1.0 when missing
+#[derive(Component)]
+pub struct FocusWeight(pub f32);
+
+// keeps the entity opaque even when it hides a focus target
+#[derive(Component)]
+pub struct NoOcclusionFade;
+
+// where the camera is looking, how far back it sits and how it's tuned, the transform is
+// derived from this
+#[derive(Component, Clone, Copy)]
+pub struct CameraRig {
+    pub focus: Vec3,
+    pub distance: f32,
+    // radians around the Y axis, 0.0 looks down -Z
+    pub yaw: f32,
+    // added to the focus point, lets the camera aim above or ahead of the subjects
+    pub offset: Vec3,
+    // angle looking down from the horizon, in radians
+    pub pitch: f32,
+    // higher values catch up faster, independent of frame rate
+    pub follow_speed: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+}
+
+impl CameraRig {
+    pub fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw) * Quat::from_rotation_x(-self.pitch)
+    }
+
+    pub fn transform(&self) -> Transform {
+        let rotation = self.rotation();
+        Transform::from_translation(self.focus + self.offset + rotation * Vec3::Z * self.distance)
+            .with_rotation(rotation)
+    }
+
+    // every zoom, from following or from cutscenes, stays within the zoom range
+    pub fn clamp_zoom(&self, distance: f32) -> f32 {
+        distance.clamp(self.min_zoom, self.max_zoom)
+    }
+
+    // fraction to move towards the target this frame
+    pub fn follow_factor(&self, delta_secs: f32) -> f32 {
+        1.0 - (-self.follow_speed * delta_secs).exp()
+    }
+}
+
+// 0..1, decays over time and drives the shake offset
+#[derive(Component, Default)]
+pub struct CameraShake {
+    pub trauma: f32,
+}
+
+// area on the X-Z plane the camera is allowed to show, one per world
+#[derive(Component)]
+pub struct CameraBounds(pub Rect);
@@ -2,3 +2,7 @@ use bevy::prelude::*;
 
 #[derive(Component)]
 pub struct Enemy;
+
+// the archetype in assets/enemies an enemy is built from
+#[derive(Component, Clone)]
+pub struct EnemyKind(pub String);
@@ -7,4 +7,5 @@ pub enum GameLayer {
     PlayerBullet,
     Floor,
     Sensor,
+    Pickup,
 }
@@ -1,15 +1,22 @@
 use bevy::prelude::*;
+
+use crate::components::world::WorldId;
+
 #[derive(States, Debug, Hash, Eq, PartialEq, Clone, Default)]
 pub enum ActivityState {
     #[default]
     Playing,
     Cutscene,
+    Dialogue,
     Pause,
 }
 
-#[derive(States, Debug, Hash, Eq, PartialEq, Clone, Default)]
-pub enum LocationState {
-    #[default]
-    Outside,
-    Inside,
+// the world the player is in, see WorldRegistry for the ones that exist
+#[derive(States, Debug, Hash, Eq, PartialEq, Clone)]
+pub struct LocationState(pub WorldId);
+
+impl Default for LocationState {
+    fn default() -> Self {
+        Self(WorldId::OUTSIDE)
+    }
 }
@@ -2,3 +2,7 @@ use bevy::prelude::*;
 
 #[derive(Component)]
 pub struct Health(pub u16);
+
+// ignores incoming damage until the timer runs out
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
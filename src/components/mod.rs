@@ -5,3 +5,4 @@ pub mod player;
 pub mod enemy;
 pub mod gamestate;
 pub mod health;
+pub mod world;
@@ -3,6 +3,10 @@ use bevy::prelude::*;
 #[derive(Component)]
 pub struct Player;
 
+// where the player is aiming, the model rotates towards it and bullets follow it
+#[derive(Component)]
+pub struct AimDirection(pub Dir3);
+
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
 pub enum PlayerSet {
     Movement,
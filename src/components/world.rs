@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// which world an entity belongs to, only the active world is visible and simulated
+#[derive(Component, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WorldId(pub Cow<'static, str>);
+
+impl WorldId {
+    pub const OUTSIDE: WorldId = WorldId::from_static("outside");
+    pub const SHELL: WorldId = WorldId::from_static("shell");
+    pub const HUT: WorldId = WorldId::from_static("hut");
+
+    pub const fn from_static(name: &'static str) -> Self {
+        Self(Cow::Borrowed(name))
+    }
+}
+
+impl std::fmt::Display for WorldId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// stable key of a named level prop, its state is saved under this while the world is unloaded
+#[derive(Component, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LevelKey(pub String);
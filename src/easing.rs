@@ -0,0 +1,134 @@
+use bevy::{
+    math::{cubic_splines::CubicSegment, curve::EaseFunction},
+    prelude::*,
+};
+use serde::Deserialize;
+
+// maps 0..1 progress to 0..1 eased progress, elastic and back overshoot in between
+#[derive(Clone, Copy, Debug, Default, PartialEq, Reflect, Deserialize)]
+pub enum Easing {
+    Linear,
+    #[default]
+    SmoothStep,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ExpoIn,
+    ExpoOut,
+    ExpoInOut,
+    ElasticIn,
+    ElasticOut,
+    ElasticInOut,
+    BackIn,
+    BackOut,
+    BackInOut,
+    // the two inner control points of a css style cubic-bezier() curve
+    CubicBezier(Vec2, Vec2),
+}
+
+impl Easing {
+    pub fn ease(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        let function = match self {
+            Easing::CubicBezier(p1, p2) => return CubicSegment::new_bezier_easing(p1, p2).ease(t),
+            Easing::Linear => EaseFunction::Linear,
+            Easing::SmoothStep => EaseFunction::SmoothStep,
+            Easing::QuadIn => EaseFunction::QuadraticIn,
+            Easing::QuadOut => EaseFunction::QuadraticOut,
+            Easing::QuadInOut => EaseFunction::QuadraticInOut,
+            Easing::CubicIn => EaseFunction::CubicIn,
+            Easing::CubicOut => EaseFunction::CubicOut,
+            Easing::CubicInOut => EaseFunction::CubicInOut,
+            Easing::ExpoIn => EaseFunction::ExponentialIn,
+            Easing::ExpoOut => EaseFunction::ExponentialOut,
+            Easing::ExpoInOut => EaseFunction::ExponentialInOut,
+            Easing::ElasticIn => EaseFunction::ElasticIn,
+            Easing::ElasticOut => EaseFunction::ElasticOut,
+            Easing::ElasticInOut => EaseFunction::ElasticInOut,
+            Easing::BackIn => EaseFunction::BackIn,
+            Easing::BackOut => EaseFunction::BackOut,
+            Easing::BackInOut => EaseFunction::BackInOut,
+        };
+        function.sample_clamped(t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Easing; 19] = [
+        Easing::Linear,
+        Easing::SmoothStep,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::ExpoIn,
+        Easing::ExpoOut,
+        Easing::ExpoInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::ElasticInOut,
+        Easing::BackIn,
+        Easing::BackOut,
+        Easing::BackInOut,
+        // css ease and ease-in-out
+        Easing::CubicBezier(Vec2::new(0.25, 0.1), Vec2::new(0.25, 1.0)),
+        Easing::CubicBezier(Vec2::new(0.42, 0.0), Vec2::new(0.58, 1.0)),
+    ];
+
+    fn overshoots(easing: Easing) -> bool {
+        matches!(
+            easing,
+            Easing::ElasticIn
+                | Easing::ElasticOut
+                | Easing::ElasticInOut
+                | Easing::BackIn
+                | Easing::BackOut
+                | Easing::BackInOut
+        )
+    }
+
+    #[test]
+    fn curves_start_at_zero_and_end_at_one() {
+        for easing in ALL {
+            assert!(easing.ease(0.0).abs() < 1e-3, "{easing:?} at 0");
+            assert!((easing.ease(1.0) - 1.0).abs() < 1e-3, "{easing:?} at 1");
+            // progress outside 0..1 is clamped
+            assert_eq!(easing.ease(-0.5), easing.ease(0.0), "{easing:?} below 0");
+            assert_eq!(easing.ease(1.5), easing.ease(1.0), "{easing:?} above 1");
+        }
+    }
+
+    #[test]
+    fn curves_without_overshoot_never_go_back() {
+        for easing in ALL.into_iter().filter(|easing| !overshoots(*easing)) {
+            let mut previous = easing.ease(0.0);
+            for step in 1..=100 {
+                let eased = easing.ease(step as f32 / 100.0);
+                assert!(eased >= previous - 1e-5, "{easing:?} at step {step}");
+                assert!(
+                    (-1e-3..=1.0 + 1e-3).contains(&eased),
+                    "{easing:?} at step {step}"
+                );
+                previous = eased;
+            }
+        }
+    }
+
+    #[test]
+    fn overshooting_curves_leave_the_range() {
+        for easing in ALL.into_iter().filter(|easing| overshoots(*easing)) {
+            let outside = (1..100)
+                .map(|step| easing.ease(step as f32 / 100.0))
+                .any(|eased| !(0.0..=1.0).contains(&eased));
+            assert!(outside, "{easing:?} stays within 0..1");
+        }
+    }
+}
@@ -1,16 +1,24 @@
 use avian3d::prelude::*;
 use bevy::{audio::AudioPlugin, prelude::*, window::WindowResolution};
 mod components;
+mod easing;
 mod plugins;
+mod ron_asset;
+mod save_migration;
 
 use plugins::{
     camera::CameraPlugin, input::InputPlugin, level::WorldPlugin, player::PlayerPlugin,
-    shooting::ShootingPlugin, world_switching::WorldSwitchingPlugin,
+    shooting::ShootingPlugin, world_streaming::WorldStreamingPlugin,
+    world_switching::WorldSwitchingPlugin,
 };
 
 use crate::{
     components::gamestate::{ActivityState, LocationState},
-    plugins::{cutscene::CutscenePlugin, inventory::InventoryPlugin},
+    plugins::{
+        cutscene::CutscenePlugin, cutscene_script::CutsceneScriptPlugin,
+        decoration::DecorationPlugin, dialogue::DialoguePlugin, enemy::EnemyPlugin,
+        inventory::InventoryPlugin, loot::LootPlugin, save::SavePlugin, waves::WavePlugin,
+    },
 };
 fn main() {
     App::new()
@@ -30,13 +38,23 @@ fn main() {
             PhysicsPlugins::default(),
             //PhysicsDebugPlugin::default(),
             CutscenePlugin,
+            CutsceneScriptPlugin,
+            DialoguePlugin,
             InputPlugin,
             PlayerPlugin,
             CameraPlugin,
             WorldPlugin,
             ShootingPlugin,
             WorldSwitchingPlugin,
+            WorldStreamingPlugin,
             InventoryPlugin,
+            DecorationPlugin,
+            SavePlugin,
+        ))
+        .add_plugins((
+            EnemyPlugin,
+            WavePlugin,
+            LootPlugin,
             // Inspector
             // EguiPlugin::default(),
             // WorldInspectorPlugin::new(),
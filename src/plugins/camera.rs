@@ -1,56 +1,496 @@
-use crate::components::camera::CameraFocus;
-use crate::components::gamestate::ActivityState;
+use crate::components::camera::{
+    CameraBounds, CameraFocus, CameraRig, CameraShake, FocusWeight, NoOcclusionFade,
+};
+use crate::components::gamelayer::GameLayer;
+use crate::components::gamestate::{ActivityState, LocationState};
+use crate::components::player::AimDirection;
+use crate::components::world::WorldId;
+use crate::easing::Easing;
+use avian3d::prelude::*;
 use bevy::{post_process::bloom::Bloom, prelude::*, render::view::Hdr};
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_camera).add_systems(
-            Update,
-            focus_camera.run_if(in_state(ActivityState::Playing)),
-        );
+        app.init_resource::<CameraSettings>()
+            .add_systems(Startup, spawn_camera)
+            .add_systems(OnExit(ActivityState::Cutscene), start_camera_transition)
+            .add_systems(
+                Update,
+                (
+                    focus_camera.run_if(in_state(ActivityState::Playing)),
+                    fade_occluders,
+                ),
+            )
+            // cutscenes move the rig too, so the transform is only written once everything is done
+            .add_systems(
+                PostUpdate,
+                (confine_camera_rig, apply_camera_rig)
+                    .chain()
+                    .before(TransformSystems::Propagate),
+            )
+            .add_observer(on_add_trauma)
+            .add_observer(on_faded_removed)
+            .register_type::<CameraSettings>();
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct CameraSettings {
+    // closest the camera gets, it only backs off further to fit every focus target
+    pub distance: f32,
+    // the tuning new camera rigs start with, see CameraRig
+    pub offset: Vec3,
+    pub pitch: f32,
+    pub follow_speed: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    // fraction of the half screen kept clear around the focus targets, 0.0 lets them touch the edges
+    pub screen_margin: f32,
+    // how far ahead of the aim direction the camera leads
+    pub look_ahead: f32,
+    // alpha of geometry hiding a focus target
+    pub occlusion_alpha: f32,
+    // alpha change per second while fading occluders in and out
+    pub occlusion_fade_speed: f32,
+    // accessibility toggle, disables all screen shake
+    pub shake_enabled: bool,
+    // multiplier for the trauma added by every source
+    pub shake_intensity: f32,
+    pub shake_max_offset: f32,
+    // radians
+    pub shake_max_roll: f32,
+    pub shake_frequency: f32,
+    // trauma lost per second
+    pub trauma_decay: f32,
+    // easing for camera transitions, cutscene moves, zooms and fades that don't pick their own
+    // and the hand back to following once a cutscene ends
+    pub transition_easing: Easing,
+    // seconds the camera takes to get back to following after a cutscene
+    pub transition_duration: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            offset: Vec3::ZERO,
+            pitch: 45_f32.to_radians(),
+            // same as the old fixed 10 up and 10 back
+            distance: 10.0 * std::f32::consts::SQRT_2,
+            // matches the old lerp of 0.1 per frame at 60fps
+            follow_speed: 6.3,
+            min_zoom: 2.0,
+            max_zoom: 40.0,
+            screen_margin: 0.2,
+            look_ahead: 1.0,
+            occlusion_alpha: 0.25,
+            occlusion_fade_speed: 4.0,
+            shake_enabled: true,
+            shake_intensity: 1.0,
+            shake_max_offset: 0.4,
+            shake_max_roll: 3_f32.to_radians(),
+            shake_frequency: 15.0,
+            trauma_decay: 1.5,
+            transition_easing: Easing::SmoothStep,
+            transition_duration: 0.6,
+        }
     }
 }
 
-fn spawn_camera(mut commands: Commands) {
+impl CameraSettings {
+    // a rig looking at `focus` from the closest distance, tuned with these settings
+    pub fn rig(&self, focus: Vec3) -> CameraRig {
+        CameraRig {
+            focus,
+            distance: self.distance,
+            yaw: 0.0,
+            offset: self.offset,
+            pitch: self.pitch,
+            follow_speed: self.follow_speed,
+            min_zoom: self.min_zoom,
+            max_zoom: self.max_zoom,
+        }
+    }
+}
+
+fn spawn_camera(mut commands: Commands, camera_settings: Res<CameraSettings>) {
+    let rig = camera_settings.rig(Vec3::ZERO);
     // camera
     commands.spawn((
         Camera3d::default(),
         Hdr,
         Bloom::default(),
-        Transform::from_xyz(0., 10., 10.0).looking_at(Vec3::ZERO, Vec3::Y),
+        rig.transform(),
+        rig,
+        CameraShake::default(),
     ));
 }
 
+// eases the rig from wherever a cutscene left it to the follow target, replaces the catch up
+// of focus_camera until it's done
+#[derive(Component)]
+struct CameraTransition {
+    from: CameraRig,
+    timer: Timer,
+}
+
+fn start_camera_transition(
+    mut commands: Commands,
+    camera_settings: Res<CameraSettings>,
+    camera: Single<(Entity, &CameraRig)>,
+) {
+    let (entity, rig) = *camera;
+    commands.entity(entity).insert(CameraTransition {
+        from: *rig,
+        timer: Timer::from_seconds(camera_settings.transition_duration, TimerMode::Once),
+    });
+}
+
 fn focus_camera(
-    mut camera_transform: Single<&mut Transform, (With<Camera>, Without<CameraFocus>)>,
-    subject_query: Query<&Transform, With<CameraFocus>>,
+    mut commands: Commands,
+    time: Res<Time>,
+    camera_settings: Res<CameraSettings>,
+    camera: Single<(
+        Entity,
+        &mut CameraRig,
+        &Projection,
+        Option<&mut CameraTransition>,
+    )>,
+    subject_query: Query<
+        (&Transform, Option<&FocusWeight>, Option<&AimDirection>),
+        With<CameraFocus>,
+    >,
 ) {
-    let mut focus_points = Vec::new();
+    let (entity, mut rig, projection, transition) = camera.into_inner();
 
-    for transform in subject_query.iter() {
-        focus_points.push(transform.translation);
+    let mut weighted_sum = Vec3::ZERO;
+    let mut total_weight = 0.0;
+    let mut aim = Vec3::ZERO;
+    for (transform, weight, aim_direction) in &subject_query {
+        let weight = weight.map_or(1.0, |weight| weight.0);
+        weighted_sum += transform.translation * weight;
+        total_weight += weight;
+        if let Some(aim_direction) = aim_direction {
+            aim += *aim_direction.0 * weight;
+        }
     }
 
-    if !focus_points.is_empty() {
-        let avg_position = focus_points.iter().sum::<Vec3>() / focus_points.len() as f32;
+    if total_weight <= 0.0 {
+        return;
+    }
+    let target_focus =
+        weighted_sum / total_weight + aim / total_weight * camera_settings.look_ahead;
 
-        // Camera follows on X-Z plane, stays high on Y-axis
-        let target_camera_pos = Vec3::new(
-            avg_position.x,        // Follow X
-            10.0,                  // Stay high on Y (was Z)
-            avg_position.z + 10.0, // Follow Z with offset (was Y)
+    // back off until every target fits inside the margin, the rotation doesn't depend on the
+    // focus or distance so the camera basis is known before the camera gets there
+    let mut target_distance = camera_settings.distance;
+    if let Projection::Perspective(perspective) = projection {
+        let rotation = rig.rotation();
+        let (right, up, forward) = (
+            rotation * Vec3::X,
+            rotation * Vec3::Y,
+            rotation * Vec3::NEG_Z,
         );
+        let tan_y = (perspective.fov * 0.5).tan() * (1.0 - camera_settings.screen_margin);
+        let tan_x = tan_y * perspective.aspect_ratio;
+        let center = target_focus + rig.offset;
+
+        for (transform, _, _) in &subject_query {
+            let relative = transform.translation - center;
+            // depth in front of the camera is distance + relative.dot(forward)
+            let depth_offset = relative.dot(forward);
+            target_distance = target_distance
+                .max(relative.dot(right).abs() / tan_x - depth_offset)
+                .max(relative.dot(up).abs() / tan_y - depth_offset);
+        }
+    }
+    let target_distance = rig.clamp_zoom(target_distance);
+
+    if let Some(mut transition) = transition {
+        transition.timer.tick(time.delta());
+        let t = camera_settings
+            .transition_easing
+            .ease(transition.timer.fraction());
+        rig.focus = transition.from.focus.lerp(target_focus, t);
+        rig.distance = transition.from.distance.lerp(target_distance, t);
+        if transition.timer.is_finished() {
+            commands.entity(entity).remove::<CameraTransition>();
+        }
+        return;
+    }
+
+    let factor = rig.follow_factor(time.delta_secs());
+    rig.focus = rig.focus.lerp(target_focus, factor);
+    rig.distance = rig.distance.lerp(target_distance, factor);
+}
+
+// the ground around the focus the camera sees, relative to the focus, None when the top
+// of the screen looks over the horizon
+fn ground_footprint(rig: &CameraRig, perspective: &PerspectiveProjection) -> Option<Rect> {
+    // project the screen corners onto the ground to find how much of it is visible
+    let rotation = rig.rotation();
+    let eye = rig.offset + rotation * Vec3::Z * rig.distance;
+    let tan_y = (perspective.fov * 0.5).tan();
+    let tan_x = tan_y * perspective.aspect_ratio;
+    let mut footprint = Rect::EMPTY;
+    for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+        let direction = rotation * Vec3::new(x * tan_x, y * tan_y, -1.0);
+        if direction.y >= 0.0 {
+            return None;
+        }
+        let ground = eye + direction * (-eye.y / direction.y);
+        footprint = footprint.union_point(Vec2::new(ground.x, ground.z));
+    }
+    Some(footprint)
+}
+
+fn confine_camera_rig(
+    current_location: Res<State<LocationState>>,
+    camera: Single<(&mut CameraRig, &Projection)>,
+    bounds_query: Query<(&CameraBounds, &WorldId)>,
+) {
+    let (mut rig, projection) = camera.into_inner();
+    let Some(bounds) = bounds_query
+        .iter()
+        .find(|(_, world)| **world == current_location.0)
+        .map(|(bounds, _)| bounds.0)
+    else {
+        return;
+    };
+    let Projection::Perspective(perspective) = projection else {
+        return;
+    };
+    let Some(mut footprint) = ground_footprint(&rig, perspective) else {
+        return;
+    };
+
+    // zoom in until the view fits inside the bounds, the footprint grows with the distance
+    let fit = (bounds.width() / footprint.width()).min(bounds.height() / footprint.height());
+    if fit < 1.0 {
+        rig.distance = rig.clamp_zoom(rig.distance * fit);
+        let Some(zoomed) = ground_footprint(&rig, perspective) else {
+            return;
+        };
+        footprint = zoomed;
+    }
+
+    // shrink the bounds by what the camera sees, if even the closest zoom sees more than the
+    // level only the focus is kept inside so the camera still follows the player
+    let clamp_axis = |value: f32, bounds_min: f32, bounds_max: f32, min: f32, max: f32| {
+        if min > max {
+            value.clamp(bounds_min, bounds_max)
+        } else {
+            value.clamp(min, max)
+        }
+    };
+    rig.focus.x = clamp_axis(
+        rig.focus.x,
+        bounds.min.x,
+        bounds.max.x,
+        bounds.min.x - footprint.min.x,
+        bounds.max.x - footprint.max.x,
+    );
+    rig.focus.z = clamp_axis(
+        rig.focus.z,
+        bounds.min.y,
+        bounds.max.y,
+        bounds.min.y - footprint.min.y,
+        bounds.max.y - footprint.max.y,
+    );
+}
+
+fn apply_camera_rig(
+    time: Res<Time>,
+    camera_settings: Res<CameraSettings>,
+    camera: Single<(&mut Transform, &CameraRig, &mut CameraShake)>,
+) {
+    let (mut transform, rig, mut shake) = camera.into_inner();
+    *transform = rig.transform();
+
+    shake.trauma = (shake.trauma - camera_settings.trauma_decay * time.delta_secs()).max(0.0);
+    if !camera_settings.shake_enabled || shake.trauma <= 0.0 {
+        return;
+    }
+
+    // squaring makes small hits subtle while big ones still kick hard
+    let amount = shake.trauma * shake.trauma;
+    let t = time.elapsed_secs() * camera_settings.shake_frequency;
+    let offset = Vec3::new(value_noise(0, t), value_noise(1, t), value_noise(2, t));
+    transform.translation += offset * camera_settings.shake_max_offset * amount;
+    transform.rotate_local_z(value_noise(3, t) * camera_settings.shake_max_roll * amount);
+}
+
+// adds trauma to the camera shake, clamped so repeated hits don't shake forever
+#[derive(Event)]
+pub struct AddTrauma(pub f32);
+
+fn on_add_trauma(
+    event: On<AddTrauma>,
+    camera_settings: Res<CameraSettings>,
+    mut shake: Single<&mut CameraShake>,
+) {
+    if camera_settings.shake_enabled {
+        shake.trauma = (shake.trauma + event.0 * camera_settings.shake_intensity).min(1.0);
+    }
+}
+
+// smooth 1d value noise in -1..1, each seed is an independent channel
+fn value_noise(seed: u32, x: f32) -> f32 {
+    let hash = |n: i32| {
+        let mut h = (n as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x9e37_79b9);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+    let cell = x.floor();
+    let fraction = x - cell;
+    let smooth = fraction * fraction * (3.0 - 2.0 * fraction);
+    hash(cell as i32).lerp(hash(cell as i32 + 1), smooth)
+}
+
+// more than this many walls between the camera and the player is unlikely
+const MAX_OCCLUDERS: u32 = 8;
+
+// swapped in for the original material while the entity hides a focus target
+#[derive(Component)]
+struct Faded {
+    original: Handle<StandardMaterial>,
+    // the copy only this entity uses, dropped with the component
+    translucent: Handle<StandardMaterial>,
+    alpha: f32,
+}
+
+// also runs when a faded entity is despawned, e.g. with the world it belongs to
+fn on_faded_removed(
+    event: On<Remove, Faded>,
+    faded: Query<&Faded>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if let Ok(faded) = faded.get(event.entity) {
+        materials.remove(&faded.translucent);
+    }
+}
+
+fn fade_occluders(
+    mut commands: Commands,
+    time: Res<Time>,
+    camera_settings: Res<CameraSettings>,
+    camera: Single<&GlobalTransform, With<CameraRig>>,
+    focus_query: Query<(Entity, &GlobalTransform), With<CameraFocus>>,
+    spatial_query: SpatialQuery,
+    mut occluders: Query<
+        (
+            Entity,
+            &mut MeshMaterial3d<StandardMaterial>,
+            Option<&mut Faded>,
+        ),
+        Without<NoOcclusionFade>,
+    >,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let camera_position = camera.translation();
+    let filter = SpatialQueryFilter::from_mask(GameLayer::Default)
+        .with_excluded_entities(focus_query.iter().map(|(entity, _)| entity));
+
+    let mut occluding = Vec::new();
+    for (_, focus_transform) in &focus_query {
+        let to_focus = focus_transform.translation() - camera_position;
+        let Ok(direction) = Dir3::new(to_focus) else {
+            continue;
+        };
+        for hit in spatial_query.ray_hits(
+            camera_position,
+            direction,
+            to_focus.length(),
+            MAX_OCCLUDERS,
+            true,
+            &filter,
+        ) {
+            occluding.push(hit.entity);
+        }
+    }
+
+    let step = camera_settings.occlusion_fade_speed * time.delta_secs();
+    for (entity, mut material, faded) in &mut occluders {
+        let occluded = occluding.contains(&entity);
+        match faded {
+            Some(mut faded) => {
+                let target = if occluded {
+                    camera_settings.occlusion_alpha
+                } else {
+                    1.0
+                };
+                faded.alpha += (target - faded.alpha).clamp(-step, step);
+
+                if faded.alpha >= 1.0 {
+                    // fully visible again, go back to the shared original
+                    material.0 = faded.original.clone();
+                    commands.entity(entity).remove::<Faded>();
+                } else {
+                    let original_alpha = materials
+                        .get(&faded.original)
+                        .map_or(1.0, |original| original.base_color.alpha());
+                    if let Some(translucent) = materials.get_mut(&faded.translucent) {
+                        translucent
+                            .base_color
+                            .set_alpha(original_alpha * faded.alpha);
+                    }
+                }
+            }
+            None if occluded => {
+                let Some(original) = materials.get(&material.0) else {
+                    continue;
+                };
+                // fade a copy so other entities sharing the material stay opaque
+                let mut translucent = original.clone();
+                translucent.alpha_mode = AlphaMode::Blend;
+                let translucent = materials.add(translucent);
+                let original = std::mem::replace(&mut material.0, translucent.clone());
+                commands.entity(entity).insert(Faded {
+                    original,
+                    translucent,
+                    alpha: 1.0,
+                });
+            }
+            None => {}
+        }
+    }
+}
 
-        camera_transform.translation = camera_transform.translation.lerp(target_camera_pos, 0.1);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin};
 
-        // Zoom based on spread
-        let max_distance = focus_points
-            .iter()
-            .map(|pos| pos.distance(avg_position))
-            .fold(0.0, f32::max);
+    #[test]
+    fn confining_zooms_within_the_rigs_own_range() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .init_state::<LocationState>();
+        app.update();
+        // far smaller than what the camera sees even from the closest zoom
+        app.world_mut().spawn((
+            CameraBounds(Rect::from_center_half_size(Vec2::ZERO, Vec2::splat(0.5))),
+            WorldId::OUTSIDE,
+        ));
+        let settings = CameraSettings::default();
+        let close_up = app
+            .world_mut()
+            .spawn((
+                CameraRig {
+                    min_zoom: 8.0,
+                    ..settings.rig(Vec3::ZERO)
+                },
+                Projection::Perspective(default()),
+            ))
+            .id();
 
-        camera_transform.translation.y = 10.0 + max_distance; // Adjust Y height for zoom
+        app.world_mut().run_system_once(confine_camera_rig).unwrap();
+        let rig = app.world().get::<CameraRig>(close_up).unwrap();
+        // CameraSettings::min_zoom would have let it come closer
+        assert_eq!(rig.distance, 8.0);
     }
 }
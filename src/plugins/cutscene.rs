@@ -1,10 +1,18 @@
 use avian3d::prelude::*;
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, platform::collections::HashSet, prelude::*};
+use serde::{Deserialize, Deserializer};
 use std::collections::VecDeque;
 
+use crate::components::camera::CameraRig;
 use crate::components::gamestate::{ActivityState, LocationState};
 use crate::components::player::Player;
-use crate::plugins::cutscene;
+use crate::components::world::WorldId;
+use crate::easing::Easing;
+use crate::plugins::camera::CameraSettings;
+use crate::plugins::cutscene_script::{CutsceneParams, Param, resolve_actions};
+use crate::plugins::dialogue::{ActiveDialogue, CloseDialogue, StartDialogue};
+use crate::plugins::input::KeyBindings;
+use crate::plugins::world_streaming::WorldStreaming;
 
 pub struct CutscenePlugin;
 
@@ -12,6 +20,7 @@ impl Plugin for CutscenePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, spawn_fade_overlay);
         app.init_resource::<CutsceneSequence>();
+        app.init_resource::<CutsceneFlags>();
         app.add_systems(
             Update,
             process_cutscene.run_if(in_state(ActivityState::Cutscene)),
@@ -22,6 +31,10 @@ impl Plugin for CutscenePlugin {
 #[derive(Component)]
 pub struct FadeOverlay;
 
+// text box spawned by CutsceneAction::ShowDialogue, despawned when the action ends
+#[derive(Component)]
+struct CutsceneDialogue;
+
 fn spawn_fade_overlay(mut commands: Commands) {
     commands.spawn((
         FadeOverlay,
@@ -36,124 +49,1096 @@ fn spawn_fade_overlay(mut commands: Commands) {
     ));
 }
 
-#[derive(Clone)]
+// entities are referenced by their Name and assets by path so sequences can be loaded
+// from cutscene scripts, the skipped fields are runtime state
+#[derive(Clone, Deserialize)]
 pub enum CutsceneAction {
     Wait(f32),
-    MoveCamera(Vec3, f32),
+    // timed actions without an easing use CameraSettings::transition_easing
+    MoveCamera {
+        target: Param<Vec3>,
+        duration: f32,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<Vec3>,
+    },
     MoveCameraToPlayer,
-    ZoomCamera(f32, f32, Option<Vec3>),
+    ZoomCamera {
+        amount: f32,
+        duration: f32,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<CameraRig>,
+    },
+    RotateCamera {
+        // radians
+        yaw: f32,
+        duration: f32,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<f32>,
+    },
     FadeAndZoom {
         amount: f32,
         duration: f32,
-        start: Option<Vec3>,
         reversed: bool,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<CameraRig>,
     },
-    MovePlayer(Vec3),
-    NextLevel(LocationState),
-    FadeToBlack,
+    MovePlayer(Param<Vec3>),
+    // a named spawn point of the current world, run after WaitForWorld
+    MovePlayerToSpawn(Param<String>),
+    NextLevel(Param<WorldId>),
+    // holds until the world entered by NextLevel has streamed in
+    WaitForWorld,
+    // the start alpha is taken from the overlay
+    FadeToBlack {
+        duration: f32,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<f32>,
+    },
+    FadeFromBlack {
+        duration: f32,
+        #[serde(default)]
+        easing: Option<Easing>,
+        #[serde(skip)]
+        start: Option<f32>,
+    },
+    PlayAnimation {
+        target: String,
+        animation: String,
+    },
+    ShowDialogue {
+        speaker: String,
+        text: String,
+        duration: f32,
+        #[serde(skip)]
+        text_box: Option<Entity>,
+    },
+    SetVisibility {
+        target: String,
+        visible: bool,
+    },
+    PlaySound(String),
+    // true once armed, so the key that started the cutscene doesn't skip it
+    WaitForInput(#[serde(skip)] bool),
+    SpawnEntity {
+        name: String,
+        scene: String,
+        #[serde(default)]
+        transform: Transform,
+    },
+    // runs every action at the same time, finishes once all of them have
+    Parallel(#[serde(deserialize_with = "deserialize_parallel")] Vec<Track>),
+    // runs the actions one after another, mostly useful inside Parallel
+    Sequence(Track),
+    // the condition is checked once when the branch starts
+    Branch {
+        condition: CutsceneCondition,
+        then: Track,
+        #[serde(default)]
+        otherwise: Track,
+        #[serde(skip)]
+        taken: Option<bool>,
+    },
+    Label(String),
+    // continues at the label, searching the enclosing tracks outwards
+    Jump(String),
+    SetFlag(String, bool),
+    // plays a dialogue asset and waits until it's closed
+    Dialogue {
+        name: String,
+        #[serde(skip)]
+        started: bool,
+    },
+}
+
+#[derive(Clone, Deserialize)]
+pub enum CutsceneCondition {
+    World(WorldId),
+    // center, radius
+    PlayerNear(Vec3, f32),
+    Flag(String),
+    Not(Box<CutsceneCondition>),
+}
+
+impl CutsceneCondition {
+    fn holds(&self, ctx: &CutsceneContext) -> bool {
+        match self {
+            CutsceneCondition::World(world) => ctx.location.0 == *world,
+            CutsceneCondition::PlayerNear(center, radius) => {
+                ctx.player.0.translation.distance(*center) <= *radius
+            }
+            CutsceneCondition::Flag(flag) => ctx.flags.0.contains(flag),
+            CutsceneCondition::Not(condition) => !condition.holds(ctx),
+        }
+    }
+}
+
+// flags set by CutsceneAction::SetFlag, so later cutscenes can branch on what already happened
+#[derive(Resource, Default)]
+pub struct CutsceneFlags(pub HashSet<String>);
+
+// keeps loops of instant actions from locking up a frame
+const MAX_STEPS_PER_FRAME: usize = 256;
+
+enum TrackStatus {
+    Running,
+    Finished,
+    Jump(String),
+}
+
+// a list of actions run in order, the running action is a clone so jumping back
+// to it starts it fresh
+#[derive(Clone, Default, Deserialize)]
+#[serde(from = "Vec<CutsceneAction>")]
+pub struct Track {
+    actions: Vec<CutsceneAction>,
+    index: usize,
+    current: Option<Box<CutsceneAction>>,
+    timer: Option<Timer>,
+}
+
+impl From<Vec<CutsceneAction>> for Track {
+    fn from(actions: Vec<CutsceneAction>) -> Self {
+        Self {
+            actions,
+            ..default()
+        }
+    }
+}
+
+fn deserialize_parallel<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Track>, D::Error> {
+    let actions = Vec::<CutsceneAction>::deserialize(deserializer)?;
+    Ok(actions
+        .into_iter()
+        .map(|action| vec![action].into())
+        .collect())
+}
+
+impl Track {
+    pub fn actions_mut(&mut self) -> &mut Vec<CutsceneAction> {
+        &mut self.actions
+    }
+
+    fn run(&mut self, ctx: &mut CutsceneContext) -> TrackStatus {
+        for _ in 0..MAX_STEPS_PER_FRAME {
+            let Some(next) = self.actions.get(self.index) else {
+                return TrackStatus::Finished;
+            };
+            let action = self.current.get_or_insert_with(|| Box::new(next.clone()));
+            match action.run(&mut self.timer, ctx) {
+                TrackStatus::Running => return TrackStatus::Running,
+                TrackStatus::Finished => self.index += 1,
+                TrackStatus::Jump(label) => {
+                    let target = self.actions.iter().position(
+                        |action| matches!(action, CutsceneAction::Label(name) if *name == label),
+                    );
+                    match target {
+                        // skipping would loop forever, it carries on after whatever jumped back
+                        Some(index) if *ctx.skipping && index <= self.index => self.index += 1,
+                        Some(index) => self.index = index,
+                        None => return TrackStatus::Jump(label),
+                    }
+                }
+            }
+            self.current = None;
+            self.timer = None;
+        }
+        warn!("cutscene ran {MAX_STEPS_PER_FRAME} actions in one frame, continuing next frame");
+        TrackStatus::Running
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CutsceneId(u32);
+
+// what happens when a cutscene is played while another one is running or queued
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum InterruptPolicy {
+    // plays after everything with the same or a higher priority
+    #[default]
+    Queue,
+    // cancels the running cutscene, queues instead if that one has a higher priority
+    Replace,
+    // dropped if anything is running or queued
+    IgnoreIfRunning,
+}
+
+pub struct Cutscene {
+    pub name: String,
+    pub actions: Vec<CutsceneAction>,
+    pub priority: i32,
+    pub policy: InterruptPolicy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CutsceneOutcome {
+    // played to the end, skipping counts as finishing
+    Finished,
+    Cancelled,
+}
+
+#[derive(Event, Clone)]
+pub struct CutsceneEnded {
+    pub id: CutsceneId,
+    pub name: String,
+    pub outcome: CutsceneOutcome,
+}
+
+struct ActiveCutscene {
+    id: CutsceneId,
+    name: String,
+    priority: i32,
+    track: Track,
+}
+
+impl ActiveCutscene {
+    fn ended(self, outcome: CutsceneOutcome) -> CutsceneEnded {
+        CutsceneEnded {
+            id: self.id,
+            name: self.name,
+            outcome,
+        }
+    }
 }
 
 impl CutsceneSequence {
-    pub fn add_actions(
+    // returns None when the policy drops the cutscene or one of its parameters was never
+    // resolved, so Track::run only ever sees values
+    pub fn play(
         &mut self,
         next_activity: &mut NextState<ActivityState>,
-        actions: Vec<CutsceneAction>,
-    ) {
+        mut cutscene: Cutscene,
+    ) -> Option<CutsceneId> {
+        if let Err(err) = resolve_actions(&mut cutscene.actions, &CutsceneParams::default()) {
+            error!("cutscene {}: {err}", cutscene.name);
+            return None;
+        }
+        if cutscene.policy == InterruptPolicy::IgnoreIfRunning && self.is_busy() {
+            return None;
+        }
+        let id = CutsceneId(self.next_id);
+        self.next_id += 1;
+        let active = ActiveCutscene {
+            id,
+            name: cutscene.name,
+            priority: cutscene.priority,
+            track: cutscene.actions.into(),
+        };
         next_activity.set(ActivityState::Cutscene);
-        self.actions.extend(actions);
+
+        match &self.running {
+            None => self.running = Some(active),
+            Some(running)
+                if cutscene.policy == InterruptPolicy::Replace
+                    && running.priority <= active.priority =>
+            {
+                let cancelled = self.running.replace(active).unwrap();
+                self.ended.push(cancelled.ended(CutsceneOutcome::Cancelled));
+            }
+            Some(_) => {
+                let index = self
+                    .queued
+                    .iter()
+                    .position(|queued| queued.priority < active.priority)
+                    .unwrap_or(self.queued.len());
+                self.queued.insert(index, active);
+            }
+        }
+        Some(id)
+    }
+
+    pub fn cancel(&mut self, id: CutsceneId) -> bool {
+        let cancelled = if self
+            .running
+            .as_ref()
+            .is_some_and(|running| running.id == id)
+        {
+            self.running.take()
+        } else {
+            let index = self.queued.iter().position(|queued| queued.id == id);
+            index.and_then(|index| self.queued.remove(index))
+        };
+        let Some(cancelled) = cancelled else {
+            return false;
+        };
+        self.ended.push(cancelled.ended(CutsceneOutcome::Cancelled));
+        true
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.running.is_some() || !self.queued.is_empty()
     }
 }
 
+// running cutscene and the ones waiting behind it, sorted by priority
 #[derive(Resource, Default)]
 pub struct CutsceneSequence {
-    actions: VecDeque<CutsceneAction>,
-    timer: Option<Timer>,
+    running: Option<ActiveCutscene>,
+    queued: VecDeque<ActiveCutscene>,
+    // ended since the last update, triggered as CutsceneEnded from process_cutscene
+    ended: Vec<CutsceneEnded>,
+    next_id: u32,
+}
+
+#[derive(SystemParam)]
+struct CutsceneContext<'w, 's> {
+    commands: Commands<'w, 's>,
+    time: Res<'w, Time>,
+    keys: Res<'w, ButtonInput<KeyCode>>,
+    mouse: Res<'w, ButtonInput<MouseButton>>,
+    location: Res<'w, State<LocationState>>,
+    next_location: ResMut<'w, NextState<LocationState>>,
+    flags: ResMut<'w, CutsceneFlags>,
+    player: Single<'w, 's, (&'static mut Transform, &'static mut Position), With<Player>>,
+    camera_rig: Single<'w, 's, &'static mut CameraRig>,
+    camera_settings: Res<'w, CameraSettings>,
+    overlay: Single<'w, 's, &'static mut BackgroundColor, With<FadeOverlay>>,
+    keybinds: Res<'w, KeyBindings>,
+    active_dialogue: Option<Res<'w, ActiveDialogue>>,
+    streaming: Res<'w, WorldStreaming>,
+    // set by the skip key, every action jumps to its end state until the cutscene is over
+    skipping: Local<'s, bool>,
+}
+
+impl CutsceneContext<'_, '_> {
+    // ticks the timer shared by the current action and returns how far along it is
+    fn progress(&self, timer: &mut Option<Timer>, duration: f32) -> f32 {
+        let timer = timer.get_or_insert(Timer::from_seconds(duration, TimerMode::Once));
+        if *self.skipping {
+            timer.finish();
+        } else {
+            timer.tick(self.time.delta());
+        }
+        timer.fraction()
+    }
+
+    fn ease(&self, easing: &Option<Easing>, t: f32) -> f32 {
+        easing
+            .unwrap_or(self.camera_settings.transition_easing)
+            .ease(t)
+    }
 }
 
 fn process_cutscene(
-    time: Res<Time>,
     mut cutscene_sequence: ResMut<CutsceneSequence>,
-    mut next_location: ResMut<NextState<LocationState>>,
     mut next_activity: ResMut<NextState<ActivityState>>,
-    mut player: Single<(&mut Transform, &mut Position), With<Player>>,
-    mut camera_transform: Single<&mut Transform, (With<Camera3d>, Without<Player>)>,
-    mut overlay: Single<&mut BackgroundColor, With<FadeOverlay>>,
+    dialogue: Query<Entity, With<CutsceneDialogue>>,
+    mut ctx: CutsceneContext,
 ) {
     // https://bevy-cheatbook.github.io/pitfalls/split-borrows.html
     let cutscene_sequence = &mut *cutscene_sequence;
 
-    while let Some(action) = cutscene_sequence.actions.front_mut() {
-        match action {
-            CutsceneAction::Wait(duration) => {
-                let timer = cutscene_sequence
-                    .timer
-                    .get_or_insert(Timer::from_seconds(*duration, TimerMode::Once));
-                timer.tick(time.delta());
+    // cancelled cutscenes leave their text boxes and overlay behind
+    let cancelled = !cutscene_sequence.ended.is_empty();
+    if cancelled {
+        for text_box in &dialogue {
+            ctx.commands.entity(text_box).despawn();
+        }
+        ctx.commands.trigger(CloseDialogue);
+        *ctx.skipping = false;
+    }
+    for ended in cutscene_sequence.ended.drain(..) {
+        ctx.commands.trigger(ended);
+    }
+
+    if ctx.keys.just_pressed(ctx.keybinds.skip_cutscene) {
+        *ctx.skipping = true;
+    }
+    loop {
+        let Some(running) = &mut cutscene_sequence.running else {
+            if let Some(next) = cutscene_sequence.queued.pop_front() {
+                cutscene_sequence.running = Some(next);
+                continue;
+            }
+            if cancelled {
+                ctx.overlay.0 = Color::srgba(0.0, 0.0, 0.0, 0.0);
+            }
+            next_activity.set(ActivityState::Playing);
+            return;
+        };
+        match running.track.run(&mut ctx) {
+            TrackStatus::Running => return,
+            TrackStatus::Finished => {}
+            TrackStatus::Jump(label) => warn!("cutscene label {label} not found"),
+        }
+        let finished = cutscene_sequence.running.take().unwrap();
+        ctx.commands
+            .trigger(finished.ended(CutsceneOutcome::Finished));
+        *ctx.skipping = false;
+    }
+}
+
+impl CutsceneAction {
+    fn run(&mut self, timer: &mut Option<Timer>, ctx: &mut CutsceneContext) -> TrackStatus {
+        let finished = match self {
+            CutsceneAction::Parallel(tracks) => {
+                let mut finished = true;
+                for track in tracks {
+                    match track.run(ctx) {
+                        TrackStatus::Running => finished = false,
+                        TrackStatus::Finished => {}
+                        jump => return jump,
+                    }
+                }
+                finished
             }
+            CutsceneAction::Sequence(track) => return track.run(ctx),
+            CutsceneAction::Branch {
+                condition,
+                then,
+                otherwise,
+                taken,
+            } => {
+                if *taken.get_or_insert_with(|| condition.holds(ctx)) {
+                    return then.run(ctx);
+                }
+                return otherwise.run(ctx);
+            }
+            CutsceneAction::Label(_) => true,
+            CutsceneAction::Jump(label) => return TrackStatus::Jump(label.clone()),
+            CutsceneAction::Dialogue { name, started } => {
+                if *ctx.skipping {
+                    if *started {
+                        ctx.commands.trigger(CloseDialogue);
+                    }
+                    true
+                } else if !*started {
+                    ctx.commands.trigger(StartDialogue(name.clone()));
+                    *started = true;
+                    false
+                } else {
+                    ctx.active_dialogue.is_none()
+                }
+            }
+            CutsceneAction::SetFlag(flag, set) => {
+                if *set {
+                    ctx.flags.0.insert(flag.clone());
+                } else {
+                    ctx.flags.0.remove(flag);
+                }
+                true
+            }
+            CutsceneAction::Wait(duration) => ctx.progress(timer, *duration) >= 1.0,
+            // CutsceneSequence::play resolves every parameter, unresolved ones are skipped
             CutsceneAction::MovePlayer(target) => {
-                player.0.translation = *target;
-                *player.1 = Position(*target);
+                let Some(&target) = target.value() else {
+                    return TrackStatus::Finished;
+                };
+                ctx.player.0.translation = target;
+                *ctx.player.1 = Position(target);
+                true
+            }
+            CutsceneAction::MovePlayerToSpawn(spawn) => {
+                let Some(spawn) = spawn.value() else {
+                    return TrackStatus::Finished;
+                };
+                match ctx.streaming.spawn_point(spawn) {
+                    Some(target) => {
+                        ctx.player.0.translation = target;
+                        *ctx.player.1 = Position(target);
+                    }
+                    None => warn!("no spawn point {spawn} in {}", ctx.location.0),
+                }
+                true
             }
             CutsceneAction::NextLevel(next_level) => {
-                next_location.set(next_level.clone());
+                let Some(next_level) = next_level.value() else {
+                    return TrackStatus::Finished;
+                };
+                ctx.next_location.set(LocationState(next_level.clone()));
+                true
+            }
+            CutsceneAction::WaitForWorld => {
+                // the new location only becomes the current state on the next frame
+                !matches!(*ctx.next_location, NextState::Pending(_))
+                    && ctx.streaming.is_ready(&ctx.location.0)
+            }
+            CutsceneAction::MoveCamera {
+                target,
+                duration,
+                easing,
+                start,
+            } => {
+                let Some(&target) = target.value() else {
+                    return TrackStatus::Finished;
+                };
+                let start = start.get_or_insert(ctx.camera_rig.focus);
+                let t = ctx.progress(timer, *duration);
+                ctx.camera_rig.focus = start.lerp(target, ctx.ease(easing, t));
+                t >= 1.0
             }
             CutsceneAction::MoveCameraToPlayer => {
-                // Todo: move camera magic numbers scattered across systems to a single CameraSettings resource (height, lerp_speed, zoom, etc)
-                camera_transform.translation =
-                    Vec3::new(player.0.translation.x, 10.0, player.0.translation.z + 10.0);
-            }
-            CutsceneAction::ZoomCamera(amount, duration, start) => {
-                let start = start.get_or_insert(camera_transform.translation);
-                let timer = cutscene_sequence
-                    .timer
-                    .get_or_insert(Timer::from_seconds(*duration, TimerMode::Once));
-                timer.tick(time.delta());
-                let t = (timer.elapsed_secs() / *duration).clamp(0.0, 1.0);
-                let smooth_t = t * t * (3.0 - 2.0 * t);
-                let target = Vec3::new(
-                    player.0.translation.x,
-                    10. - *amount,
-                    player.0.translation.z + 10. - *amount,
-                );
-                camera_transform.translation = start.lerp(target, smooth_t);
+                ctx.camera_rig.focus = ctx.player.0.translation;
+                ctx.camera_rig.distance = ctx.camera_settings.distance;
+                true
             }
-            CutsceneAction::FadeAndZoom {
+            CutsceneAction::ZoomCamera {
                 amount,
                 duration,
+                easing,
+                start,
+            } => {
+                let start = start.get_or_insert(**ctx.camera_rig);
+                let t = ctx.progress(timer, *duration);
+                let eased_t = ctx.ease(easing, t);
+                ctx.camera_rig.focus = start.focus.lerp(ctx.player.0.translation, eased_t);
+                let target_distance = ctx
+                    .camera_rig
+                    .clamp_zoom(ctx.camera_settings.distance - *amount);
+                ctx.camera_rig.distance = start.distance.lerp(target_distance, eased_t);
+                t >= 1.0
+            }
+            CutsceneAction::RotateCamera {
+                yaw,
+                duration,
+                easing,
                 start,
+            } => {
+                let start = start.get_or_insert(ctx.camera_rig.yaw);
+                let t = ctx.progress(timer, *duration);
+                ctx.camera_rig.yaw = start.lerp(*yaw, ctx.ease(easing, t));
+                t >= 1.0
+            }
+            CutsceneAction::FadeAndZoom {
+                amount,
+                duration,
                 reversed,
+                easing,
+                start,
             } => {
-                let start = start.get_or_insert(camera_transform.translation);
-                let timer = cutscene_sequence
-                    .timer
-                    .get_or_insert(Timer::from_seconds(*duration, TimerMode::Once));
-                timer.tick(time.delta());
-                let t = (timer.elapsed_secs() / *duration).clamp(0.0, 1.0);
-                let smooth_t = t * t * (3.0 - 2.0 * t);
-                let smooth_t = if *reversed { 1.0 - smooth_t } else { smooth_t };
-                let target = Vec3::new(
-                    player.0.translation.x,
-                    10. - *amount,
-                    player.0.translation.z + 10. - *amount,
-                );
-                camera_transform.translation = start.lerp(target, smooth_t);
-                overlay.0 = Color::srgba(0.0, 0.0, 0.0, smooth_t);
-            }
-            _ => {
-                println!("action not implemented yet");
-            }
-        }
-
-        if cutscene_sequence
-            .timer
-            .as_ref()
-            .is_none_or(|timer| timer.is_finished())
-        {
-            cutscene_sequence.actions.pop_front();
-            cutscene_sequence.timer = None;
+                let start = start.get_or_insert(**ctx.camera_rig);
+                let t = ctx.progress(timer, *duration);
+                let eased_t = ctx.ease(easing, t);
+                let eased_t = if *reversed { 1.0 - eased_t } else { eased_t };
+                ctx.camera_rig.focus = start.focus.lerp(ctx.player.0.translation, eased_t);
+                let target_distance = ctx
+                    .camera_rig
+                    .clamp_zoom(ctx.camera_settings.distance - *amount);
+                ctx.camera_rig.distance = start.distance.lerp(target_distance, eased_t);
+                ctx.overlay.0 = Color::srgba(0.0, 0.0, 0.0, eased_t.clamp(0.0, 1.0));
+                t >= 1.0
+            }
+            CutsceneAction::FadeToBlack {
+                duration,
+                easing,
+                start,
+            } => {
+                let start = start.get_or_insert(ctx.overlay.0.alpha());
+                let t = ctx.progress(timer, *duration);
+                let alpha = start.lerp(1.0, ctx.ease(easing, t)).clamp(0.0, 1.0);
+                ctx.overlay.0 = Color::srgba(0.0, 0.0, 0.0, alpha);
+                t >= 1.0
+            }
+            CutsceneAction::FadeFromBlack {
+                duration,
+                easing,
+                start,
+            } => {
+                let start = start.get_or_insert(ctx.overlay.0.alpha());
+                let t = ctx.progress(timer, *duration);
+                let alpha = start.lerp(0.0, ctx.ease(easing, t)).clamp(0.0, 1.0);
+                ctx.overlay.0 = Color::srgba(0.0, 0.0, 0.0, alpha);
+                t >= 1.0
+            }
+            CutsceneAction::PlayAnimation { target, animation } => {
+                let (target, animation) = (target.clone(), animation.clone());
+                ctx.commands
+                    .queue(move |world: &mut World| play_animation(world, &target, &animation));
+                true
+            }
+            CutsceneAction::ShowDialogue {
+                speaker,
+                text,
+                duration,
+                text_box,
+            } => {
+                if *ctx.skipping {
+                    if let Some(text_box) = text_box.take() {
+                        ctx.commands.entity(text_box).despawn();
+                    }
+                    return TrackStatus::Finished;
+                }
+                let text_box = *text_box
+                    .get_or_insert_with(|| spawn_dialogue(&mut ctx.commands, speaker, text));
+                let finished = ctx.progress(timer, *duration) >= 1.0;
+                if finished {
+                    ctx.commands.entity(text_box).despawn();
+                }
+                finished
+            }
+            CutsceneAction::SetVisibility { target, visible } => {
+                let target = target.clone();
+                let visibility = if *visible {
+                    Visibility::Inherited
+                } else {
+                    Visibility::Hidden
+                };
+                ctx.commands.queue(move |world: &mut World| {
+                    if let Some(entity) = find_named(world, &target) {
+                        world.entity_mut(entity).insert(visibility);
+                    }
+                });
+                true
+            }
+            // sounds don't leave anything behind, so skipping drops them
+            CutsceneAction::PlaySound(_) if *ctx.skipping => true,
+            CutsceneAction::PlaySound(path) => {
+                let path = path.clone();
+                ctx.commands.queue(move |world: &mut World| {
+                    // the game runs without AudioPlugin, loading a sound would panic then
+                    if !world.contains_resource::<Assets<AudioSource>>() {
+                        warn!("audio is disabled, skipping sound {path}");
+                        return;
+                    }
+                    let sound = world.resource::<AssetServer>().load(path);
+                    world.spawn((AudioPlayer::new(sound), PlaybackSettings::DESPAWN));
+                });
+                true
+            }
+            CutsceneAction::WaitForInput(armed) => {
+                let pressed =
+                    ctx.keys.get_just_pressed().len() > 0 || ctx.mouse.get_just_pressed().len() > 0;
+                let finished = *ctx.skipping || (*armed && pressed);
+                *armed = true;
+                finished
+            }
+            CutsceneAction::SpawnEntity {
+                name,
+                scene,
+                transform,
+            } => {
+                let (name, scene, transform) = (name.clone(), scene.clone(), *transform);
+                ctx.commands.queue(move |world: &mut World| {
+                    let scene = world.resource::<AssetServer>().load(scene);
+                    world.spawn((SceneRoot(scene), transform, Name::new(name)));
+                });
+                true
+            }
+        };
+        if finished {
+            TrackStatus::Finished
         } else {
+            TrackStatus::Running
+        }
+    }
+}
+
+fn find_named(world: &mut World, target: &str) -> Option<Entity> {
+    let entity = world
+        .query::<(Entity, &Name)>()
+        .iter(world)
+        .find(|(_, name)| name.as_str() == target)
+        .map(|(entity, _)| entity);
+    if entity.is_none() {
+        warn!("cutscene target {target} not found");
+    }
+    entity
+}
+
+fn play_animation(world: &mut World, target: &str, animation: &str) {
+    let Some(root) = find_named(world, target) else {
+        return;
+    };
+    let clip = world.resource::<AssetServer>().load(animation.to_owned());
+    let (graph, node) = AnimationGraph::from_clip(clip);
+    let graph = world.resource_mut::<Assets<AnimationGraph>>().add(graph);
+
+    // gltf scenes put the animation player somewhere below the root
+    let mut pending = vec![root];
+    while let Some(entity) = pending.pop() {
+        if let Some(mut animation_player) = world.get_mut::<AnimationPlayer>(entity) {
+            animation_player.stop_all();
+            animation_player.play(node);
+            world.entity_mut(entity).insert(AnimationGraphHandle(graph));
             return;
         }
+        if let Some(children) = world.get::<Children>(entity) {
+            pending.extend(children.iter());
+        }
+    }
+    warn!("cutscene target {target} has no animation player");
+}
+
+fn spawn_dialogue(commands: &mut Commands, speaker: &str, text: &str) -> Entity {
+    commands
+        .spawn((
+            CutsceneDialogue,
+            Node {
+                width: Val::Percent(60.0),
+                position_type: PositionType::Absolute,
+                left: Val::Percent(20.0),
+                bottom: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(15.0)),
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            BackgroundColor((Srgba::new(0.15, 0.15, 0.15, 0.9)).into()),
+            ZIndex(998),
+        ))
+        .with_children(|text_box| {
+            text_box.spawn((
+                Text::new(speaker),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.0, 0.4, 0.8)),
+            ));
+            text_box.spawn((
+                Text::new(text),
+                TextFont {
+                    font_size: 18.0,
+                    ..default()
+                },
+                TextColor(Color::WHITE),
+            ));
+        })
+        .id()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    // every update advances time by this much
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[derive(Resource)]
+    struct TestTrack {
+        track: Track,
+        finished: bool,
+        skipping: bool,
+    }
+
+    fn run_test_track(mut test: ResMut<TestTrack>, mut ctx: CutsceneContext) {
+        *ctx.skipping = test.skipping;
+        if !test.finished {
+            test.finished = matches!(test.track.run(&mut ctx), TrackStatus::Finished);
+        }
+    }
+
+    fn cutscene_app(actions: Vec<CutsceneAction>) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, AssetPlugin::default()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(FRAME))
+            .init_state::<LocationState>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<CutsceneFlags>()
+            .init_resource::<CameraSettings>()
+            .init_resource::<KeyBindings>()
+            .init_resource::<WorldStreaming>()
+            .init_asset::<Scene>()
+            .init_asset::<AnimationClip>()
+            .init_asset::<AnimationGraph>()
+            .insert_resource(TestTrack {
+                track: actions.into(),
+                finished: false,
+                skipping: false,
+            })
+            .add_systems(Update, run_test_track);
+        app.world_mut()
+            .spawn((Player, Transform::default(), Position::default()));
+        app.world_mut().spawn(CameraRig {
+            distance: 10.0,
+            ..CameraSettings::default().rig(Vec3::ZERO)
+        });
+        app.world_mut().spawn((
+            FadeOverlay,
+            BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.0)),
+        ));
+        app
+    }
+
+    fn finished(app: &App) -> bool {
+        app.world().resource::<TestTrack>().finished
+    }
+
+    // updates until the track finishes, failing if it takes more than `max_frames`
+    fn run_to_end(app: &mut App, max_frames: usize) {
+        for _ in 0..max_frames {
+            app.update();
+            if finished(app) {
+                return;
+            }
+        }
+        panic!("cutscene didn't finish within {max_frames} frames");
+    }
+
+    fn rig(app: &mut App) -> CameraRig {
+        *app.world_mut()
+            .query::<&CameraRig>()
+            .single(app.world())
+            .unwrap()
+    }
+
+    fn overlay_alpha(app: &mut App) -> f32 {
+        app.world_mut()
+            .query_filtered::<&BackgroundColor, With<FadeOverlay>>()
+            .single(app.world())
+            .unwrap()
+            .0
+            .alpha()
+    }
+
+    fn named(app: &mut App, name: &str) -> Option<Entity> {
+        app.world_mut()
+            .query::<(Entity, &Name)>()
+            .iter(app.world())
+            .find(|(_, entity_name)| entity_name.as_str() == name)
+            .map(|(entity, _)| entity)
+    }
+
+    #[test]
+    fn move_camera_eases_to_the_target() {
+        let target = Vec3::new(4.0, 0.0, -2.0);
+        let mut app = cutscene_app(vec![CutsceneAction::MoveCamera {
+            target: target.into(),
+            duration: 1.0,
+            easing: Some(Easing::Linear),
+            start: None,
+        }]);
+        // the first update has no time delta
+        app.update();
+        app.update();
+        let focus = rig(&mut app).focus;
+        assert!(!finished(&app));
+        assert!(focus.length() > 0.0 && focus.length() < target.length());
+
+        run_to_end(&mut app, 20);
+        assert_eq!(rig(&mut app).focus, target);
+    }
+
+    #[test]
+    fn rotate_camera_ends_at_the_yaw() {
+        let mut app = cutscene_app(vec![CutsceneAction::RotateCamera {
+            yaw: 1.5,
+            duration: 0.5,
+            easing: None,
+            start: None,
+        }]);
+        app.update();
+        assert!(!finished(&app));
+
+        run_to_end(&mut app, 20);
+        assert_eq!(rig(&mut app).yaw, 1.5);
+    }
+
+    #[test]
+    fn fade_to_black_and_back() {
+        let mut app = cutscene_app(vec![
+            CutsceneAction::FadeToBlack {
+                duration: 0.5,
+                easing: None,
+                start: None,
+            },
+            CutsceneAction::Wait(0.2),
+        ]);
+        // the first update has no time delta
+        app.update();
+        app.update();
+        let alpha = overlay_alpha(&mut app);
+        assert!(alpha > 0.0 && alpha < 1.0);
+        run_to_end(&mut app, 20);
+        assert_eq!(overlay_alpha(&mut app), 1.0);
+
+        let mut test = app.world_mut().resource_mut::<TestTrack>();
+        test.track = vec![CutsceneAction::FadeFromBlack {
+            duration: 0.5,
+            easing: Some(Easing::QuadOut),
+            start: None,
+        }]
+        .into();
+        test.finished = false;
+        run_to_end(&mut app, 20);
+        assert_eq!(overlay_alpha(&mut app), 0.0);
+    }
+
+    #[test]
+    fn play_animation_starts_the_player_below_the_target() {
+        let mut app = cutscene_app(vec![CutsceneAction::PlayAnimation {
+            target: "Turtle".into(),
+            animation: "turtle/Turtle.gltf#Animation0".into(),
+        }]);
+        let animation_player = app.world_mut().spawn(AnimationPlayer::default()).id();
+        app.world_mut()
+            .spawn(Name::new("Turtle"))
+            .add_child(animation_player);
+
+        run_to_end(&mut app, 2);
+        app.update();
+        let world = app.world();
+        assert!(
+            world
+                .get::<AnimationGraphHandle>(animation_player)
+                .is_some()
+        );
+        let playing = world
+            .get::<AnimationPlayer>(animation_player)
+            .unwrap()
+            .playing_animations()
+            .count();
+        assert_eq!(playing, 1);
+    }
+
+    #[test]
+    fn show_dialogue_removes_the_text_box_when_done() {
+        let mut app = cutscene_app(vec![CutsceneAction::ShowDialogue {
+            speaker: "Hermit".into(),
+            text: "Hello".into(),
+            duration: 0.5,
+            text_box: None,
+        }]);
+        let mut text_boxes = app
+            .world_mut()
+            .query_filtered::<Entity, With<CutsceneDialogue>>();
+        app.update();
+        assert!(!finished(&app));
+        assert_eq!(text_boxes.iter(app.world()).count(), 1);
+
+        run_to_end(&mut app, 20);
+        app.update();
+        assert_eq!(text_boxes.iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn set_visibility_hides_and_shows_the_target() {
+        let mut app = cutscene_app(vec![CutsceneAction::SetVisibility {
+            target: "Hermit".into(),
+            visible: false,
+        }]);
+        let hermit = app.world_mut().spawn(Name::new("Hermit")).id();
+        run_to_end(&mut app, 2);
+        app.update();
+        assert_eq!(
+            app.world().get::<Visibility>(hermit),
+            Some(&Visibility::Hidden)
+        );
+
+        let mut test = app.world_mut().resource_mut::<TestTrack>();
+        test.track = vec![CutsceneAction::SetVisibility {
+            target: "Hermit".into(),
+            visible: true,
+        }]
+        .into();
+        test.finished = false;
+        run_to_end(&mut app, 2);
+        app.update();
+        assert_eq!(
+            app.world().get::<Visibility>(hermit),
+            Some(&Visibility::Inherited)
+        );
+    }
+
+    #[test]
+    fn play_sound_spawns_an_audio_player() {
+        let mut app = cutscene_app(vec![CutsceneAction::PlaySound("sounds/door.ogg".into())]);
+        app.init_asset::<AudioSource>();
+        run_to_end(&mut app, 2);
+        app.update();
+        let mut sounds = app.world_mut().query::<&AudioPlayer>();
+        assert_eq!(sounds.iter(app.world()).count(), 1);
+    }
+
+    #[test]
+    fn play_sound_without_audio_is_skipped() {
+        // like the game, which disables AudioPlugin
+        let mut app = cutscene_app(vec![CutsceneAction::PlaySound("sounds/door.ogg".into())]);
+        run_to_end(&mut app, 2);
+        app.update();
+        let mut sounds = app.world_mut().query::<&AudioPlayer>();
+        assert_eq!(sounds.iter(app.world()).count(), 0);
+    }
+
+    #[test]
+    fn wait_for_input_waits_for_a_fresh_press() {
+        let mut app = cutscene_app(vec![CutsceneAction::WaitForInput(false)]);
+        // held down when the action starts, so it doesn't count
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyE);
+        app.update();
+        assert!(!finished(&app));
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .clear();
+        for _ in 0..5 {
+            app.update();
+        }
+        assert!(!finished(&app));
+
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Space);
+        app.update();
+        assert!(finished(&app));
+    }
+
+    #[test]
+    fn spawn_entity_spawns_the_scene() {
+        let transform = Transform::from_xyz(1.0, 0.0, 2.0);
+        let mut app = cutscene_app(vec![CutsceneAction::SpawnEntity {
+            name: "Crab".into(),
+            scene: "crab/Crab.gltf#Scene0".into(),
+            transform,
+        }]);
+        run_to_end(&mut app, 2);
+        app.update();
+        let crab = named(&mut app, "Crab").expect("the entity was spawned");
+        assert!(app.world().get::<SceneRoot>(crab).is_some());
+        assert_eq!(app.world().get::<Transform>(crab), Some(&transform));
+    }
+
+    fn flag_set(app: &App, flag: &str) -> bool {
+        app.world().resource::<CutsceneFlags>().0.contains(flag)
+    }
+
+    fn looping_actions() -> Vec<CutsceneAction> {
+        vec![
+            CutsceneAction::Label("loop".into()),
+            CutsceneAction::Wait(0.5),
+            CutsceneAction::Sequence(
+                vec![CutsceneAction::Branch {
+                    condition: CutsceneCondition::Not(Box::new(CutsceneCondition::Flag(
+                        "done".into(),
+                    ))),
+                    then: vec![CutsceneAction::Jump("loop".into())].into(),
+                    otherwise: Track::default(),
+                    taken: None,
+                }]
+                .into(),
+            ),
+            CutsceneAction::Wait(0.5),
+            CutsceneAction::Jump("loop".into()),
+            CutsceneAction::SetFlag("after".into(), true),
+        ]
+    }
+
+    #[test]
+    fn loops_keep_running_until_skipped() {
+        let mut app = cutscene_app(looping_actions());
+        for _ in 0..30 {
+            app.update();
+        }
+        assert!(!finished(&app));
+
+        app.world_mut().resource_mut::<TestTrack>().skipping = true;
+        app.update();
+        assert!(finished(&app));
+        assert!(flag_set(&app, "after"));
     }
-    next_activity.set(ActivityState::Playing);
 }
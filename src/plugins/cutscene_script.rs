@@ -0,0 +1,407 @@
+use avian3d::prelude::*;
+use bevy::{
+    asset::LoadedFolder, ecs::system::SystemParam, platform::collections::HashMap, prelude::*,
+};
+use serde::{Deserialize, Deserializer, de::Error};
+
+use crate::components::gamestate::ActivityState;
+use crate::components::player::Player;
+use crate::components::world::WorldId;
+use crate::plugins::cutscene::{
+    Cutscene, CutsceneAction, CutsceneId, CutsceneSequence, InterruptPolicy,
+};
+use crate::ron_asset::{RonAsset, RonAssetLoader};
+
+pub struct CutsceneScriptPlugin;
+
+impl Plugin for CutsceneScriptPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<CutsceneScript>()
+            .init_asset_loader::<RonAssetLoader<CutsceneScript>>()
+            .add_systems(Startup, load_cutscene_library)
+            .add_observer(on_play_cutscene)
+            .add_observer(on_cutscene_trigger_entered);
+    }
+}
+
+const CUTSCENE_FOLDER: &str = "cutscenes";
+
+// a sequence of actions loaded from assets/cutscenes/<name>.cutscene.ron
+#[derive(Asset, TypePath, Deserialize)]
+pub struct CutsceneScript {
+    pub actions: Vec<CutsceneAction>,
+}
+
+impl RonAsset for CutsceneScript {
+    const EXTENSION: &'static str = "cutscene.ron";
+}
+
+impl CutsceneScript {
+    // fills in every named parameter, fails on the first one that's missing or has the wrong type
+    pub fn instantiate(&self, params: &CutsceneParams) -> Result<Vec<CutsceneAction>, String> {
+        let mut actions = self.actions.clone();
+        resolve_actions(&mut actions, params)?;
+        Ok(actions)
+    }
+}
+
+// fills in the named parameters of the actions and everything nested in them
+pub fn resolve_actions(
+    actions: &mut [CutsceneAction],
+    params: &CutsceneParams,
+) -> Result<(), String> {
+    for action in actions {
+        resolve_action(action, params)?;
+    }
+    Ok(())
+}
+
+fn resolve_action(action: &mut CutsceneAction, params: &CutsceneParams) -> Result<(), String> {
+    let tracks = match action {
+        CutsceneAction::MoveCamera { target, .. } => return target.resolve(params),
+        CutsceneAction::MovePlayer(target) => return target.resolve(params),
+        CutsceneAction::MovePlayerToSpawn(spawn) => return spawn.resolve(params),
+        CutsceneAction::NextLevel(world) => return world.resolve(params),
+        CutsceneAction::Parallel(tracks) => tracks.iter_mut().collect(),
+        CutsceneAction::Sequence(track) => vec![track],
+        CutsceneAction::Branch {
+            then, otherwise, ..
+        } => vec![then, otherwise],
+        _ => return Ok(()),
+    };
+    for track in tracks {
+        resolve_actions(track.actions_mut(), params)?;
+    }
+    Ok(())
+}
+
+// either a literal in the script or a parameter passed when it's played, written as
+// "$name" so it can't be mistaken for a literal string like a world id
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Param<T> {
+    Named(ParamName),
+    Value(T),
+}
+
+#[derive(Clone)]
+pub struct ParamName(String);
+
+impl<'de> Deserialize<'de> for ParamName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match name.strip_prefix('$') {
+            Some(name) => Ok(ParamName(name.to_owned())),
+            None => Err(D::Error::custom("parameter names start with $")),
+        }
+    }
+}
+
+impl<T> From<T> for Param<T> {
+    fn from(value: T) -> Self {
+        Param::Value(value)
+    }
+}
+
+impl<T: FromParamValue> Param<T> {
+    // None until the parameter has been resolved
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Param::Value(value) => Some(value),
+            Param::Named(_) => None,
+        }
+    }
+
+    fn resolve(&mut self, params: &CutsceneParams) -> Result<(), String> {
+        if let Param::Named(ParamName(name)) = self {
+            let value = params
+                .get(name.as_str())
+                .ok_or_else(|| format!("missing cutscene parameter {name}"))?;
+            let value = T::from_param_value(value)
+                .ok_or_else(|| format!("cutscene parameter {name} has the wrong type"))?;
+            *self = Param::Value(value);
+        }
+        Ok(())
+    }
+}
+
+// written as e.g. Vec3((1.0, 0.0, 2.0)) where levels pass parameters
+#[derive(Clone, Deserialize)]
+pub enum ParamValue {
+    Vec3(Vec3),
+    World(WorldId),
+    Text(String),
+}
+
+impl From<Vec3> for ParamValue {
+    fn from(value: Vec3) -> Self {
+        ParamValue::Vec3(value)
+    }
+}
+
+impl From<WorldId> for ParamValue {
+    fn from(value: WorldId) -> Self {
+        ParamValue::World(value)
+    }
+}
+
+impl From<String> for ParamValue {
+    fn from(value: String) -> Self {
+        ParamValue::Text(value)
+    }
+}
+
+pub trait FromParamValue: Sized {
+    fn from_param_value(value: &ParamValue) -> Option<Self>;
+}
+
+impl FromParamValue for Vec3 {
+    fn from_param_value(value: &ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Vec3(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromParamValue for WorldId {
+    fn from_param_value(value: &ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::World(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromParamValue for String {
+    fn from_param_value(value: &ParamValue) -> Option<Self> {
+        match value {
+            ParamValue::Text(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+pub type CutsceneParams = HashMap<String, ParamValue>;
+
+// keeps every script loaded so they can be played the moment they're triggered
+#[derive(Resource)]
+struct CutsceneLibrary {
+    _folder: Handle<LoadedFolder>,
+}
+
+fn load_cutscene_library(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(CutsceneLibrary {
+        _folder: asset_server.load_folder(CUTSCENE_FOLDER),
+    });
+}
+
+// plays the script with the given name, parameters fill in its named values
+#[derive(Event)]
+pub struct PlayCutscene {
+    pub name: String,
+    pub params: CutsceneParams,
+    pub priority: i32,
+    pub policy: InterruptPolicy,
+}
+
+impl PlayCutscene {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            params: CutsceneParams::default(),
+            priority: 0,
+            policy: InterruptPolicy::default(),
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_policy(mut self, policy: InterruptPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_param(mut self, name: impl Into<String>, value: impl Into<ParamValue>) -> Self {
+        self.params.insert(name.into(), value.into());
+        self
+    }
+}
+
+// plays scripts right away, for callers that need to know whether the cutscene went ahead
+#[derive(SystemParam)]
+pub struct CutsceneScripts<'w> {
+    asset_server: Res<'w, AssetServer>,
+    scripts: Res<'w, Assets<CutsceneScript>>,
+    next_activity: ResMut<'w, NextState<ActivityState>>,
+    pub sequence: ResMut<'w, CutsceneSequence>,
+}
+
+impl CutsceneScripts<'_> {
+    // None when the script isn't loaded, is missing parameters or was dropped by its policy
+    pub fn play(&mut self, request: &PlayCutscene) -> Option<CutsceneId> {
+        let handle: Handle<CutsceneScript> = self
+            .asset_server
+            .load(format!("{CUTSCENE_FOLDER}/{}.cutscene.ron", request.name));
+        let Some(script) = self.scripts.get(&handle) else {
+            warn!("cutscene {} is not loaded", request.name);
+            return None;
+        };
+        let actions = match script.instantiate(&request.params) {
+            Ok(actions) => actions,
+            Err(err) => {
+                error!("cutscene {}: {err}", request.name);
+                return None;
+            }
+        };
+        let played = self.sequence.play(
+            &mut self.next_activity,
+            Cutscene {
+                name: request.name.clone(),
+                actions,
+                priority: request.priority,
+                policy: request.policy,
+            },
+        );
+        if played.is_none() {
+            debug!("cutscene {} ignored, another one is playing", request.name);
+        }
+        played
+    }
+}
+
+fn on_play_cutscene(event: On<PlayCutscene>, mut scripts: CutsceneScripts) {
+    scripts.play(&event);
+}
+
+// sensor volume that plays a cutscene when the player walks in, needs CollisionEventsEnabled
+#[derive(Component)]
+pub struct CutsceneTrigger {
+    pub script: String,
+    pub params: CutsceneParams,
+}
+
+fn on_cutscene_trigger_entered(
+    event: On<CollisionStart>,
+    triggers: Query<&CutsceneTrigger>,
+    player_query: Query<&Player>,
+    current_activity: Res<State<ActivityState>>,
+    mut commands: Commands,
+) {
+    if *current_activity != ActivityState::Playing {
+        return;
+    }
+    let (trigger, other) = if triggers.contains(event.collider1) {
+        (event.collider1, event.collider2)
+    } else {
+        (event.collider2, event.collider1)
+    };
+    if !player_query.contains(other) {
+        return;
+    }
+    if let Ok(trigger) = triggers.get(trigger) {
+        // sensors report a new contact every time the player brushes past
+        let mut request =
+            PlayCutscene::new(trigger.script.clone()).with_policy(InterruptPolicy::IgnoreIfRunning);
+        request.params = trigger.params.clone();
+        commands.trigger(request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::level::{Level, PropKind};
+
+    fn door_transition() -> CutsceneScript {
+        let source = std::fs::read_to_string("assets/cutscenes/door_transition.cutscene.ron")
+            .expect("the door transition is checked in");
+        ron::from_str(&source).expect("the door transition parses")
+    }
+
+    fn door_params() -> CutsceneParams {
+        PlayCutscene::new("door_transition")
+            .with_param("world", WorldId::SHELL)
+            .with_param("spawn", "entrance".to_owned())
+            .params
+    }
+
+    #[test]
+    fn instantiate_fills_in_every_parameter() {
+        let actions = door_transition().instantiate(&door_params()).unwrap();
+        let next_level = actions.iter().find_map(|action| match action {
+            CutsceneAction::NextLevel(world) => world.value(),
+            _ => None,
+        });
+        assert_eq!(next_level, Some(&WorldId::SHELL));
+        let spawn = actions.iter().find_map(|action| match action {
+            CutsceneAction::MovePlayerToSpawn(spawn) => spawn.value(),
+            _ => None,
+        });
+        assert_eq!(spawn.map(String::as_str), Some("entrance"));
+    }
+
+    #[test]
+    fn instantiate_fails_on_missing_or_mistyped_parameters() {
+        let script = door_transition();
+        let mut params = door_params();
+        params.remove("spawn");
+        assert!(script.instantiate(&params).is_err());
+
+        params.insert("spawn".to_owned(), Vec3::ZERO.into());
+        assert!(script.instantiate(&params).is_err());
+    }
+
+    #[test]
+    fn level_triggers_pass_the_parameters_their_script_needs() {
+        let source = std::fs::read_to_string("assets/levels/outside.level.ron")
+            .expect("the outside level is checked in");
+        let level: Level = ron::from_str(&source).expect("the outside level parses");
+        let triggers: Vec<_> = level
+            .props
+            .iter()
+            .filter_map(|prop| match &prop.kind {
+                PropKind::CutsceneTrigger { script, params } => Some((script, params)),
+                _ => None,
+            })
+            .collect();
+        assert!(!triggers.is_empty());
+        for (script, params) in triggers {
+            let source =
+                std::fs::read_to_string(format!("assets/{CUTSCENE_FOLDER}/{script}.cutscene.ron"))
+                    .unwrap_or_else(|_| panic!("no cutscene {script}"));
+            let script: CutsceneScript = ron::from_str(&source).unwrap();
+            script.instantiate(params).unwrap();
+        }
+    }
+
+    #[test]
+    fn play_refuses_unresolved_parameters() {
+        let mut sequence = CutsceneSequence::default();
+        let mut next_activity = NextState::<ActivityState>::default();
+        let cutscene = |actions| Cutscene {
+            name: "door_transition".to_owned(),
+            actions,
+            priority: 0,
+            policy: InterruptPolicy::default(),
+        };
+
+        let unresolved = door_transition().actions;
+        assert!(
+            sequence
+                .play(&mut next_activity, cutscene(unresolved))
+                .is_none()
+        );
+        assert!(!sequence.is_busy());
+
+        let resolved = door_transition().instantiate(&door_params()).unwrap();
+        assert!(
+            sequence
+                .play(&mut next_activity, cutscene(resolved))
+                .is_some()
+        );
+    }
+}
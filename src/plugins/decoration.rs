@@ -0,0 +1,389 @@
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{
+    components::{
+        gamelayer::GameLayer,
+        gamestate::{ActivityState, LocationState},
+        player::{AimDirection, Player},
+        world::WorldId,
+    },
+    plugins::{
+        input::KeyBindings,
+        inventory::{PlayerInventory, item_color},
+        world_streaming::WorldSpawned,
+    },
+};
+
+pub struct DecorationPlugin;
+
+impl Plugin for DecorationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DecorationSettings>()
+            .init_resource::<ShellLayout>()
+            .init_resource::<PlacementMode>()
+            .init_resource::<FurnitureAssets>()
+            .add_systems(
+                Update,
+                (placement_input, update_placement_preview)
+                    .chain()
+                    .run_if(in_state(LocationState(WorldId::SHELL)))
+                    .run_if(in_state(ActivityState::Playing)),
+            )
+            .add_observer(on_place_furniture)
+            .add_observer(on_pick_up_furniture)
+            .add_observer(on_shell_spawned)
+            .register_type::<DecorationSettings>();
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct DecorationSettings {
+    // furniture snaps to a grid of this size on the shell floor
+    grid_size: f32,
+    // how far in front of the player the placement cell is picked
+    reach: f32,
+}
+
+impl Default for DecorationSettings {
+    fn default() -> Self {
+        Self {
+            grid_size: 1.0,
+            reach: 1.0,
+        }
+    }
+}
+
+const FURNITURE_SIZE: Vec3 = Vec3::new(0.8, 0.5, 0.4);
+// shrinks the placement check so furniture can stand flush against walls and other furniture
+const PLACEMENT_MARGIN: f32 = 0.05;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlacedFurniture {
+    pub item: String,
+    pub cell: IVec2,
+    // quarter turns around the Y axis
+    pub rotation: u8,
+}
+
+// furniture in the shell, outlives the shell entities so it can be respawned on entry
+#[derive(Resource, Default)]
+pub struct ShellLayout {
+    pub furniture: Vec<PlacedFurniture>,
+}
+
+impl ShellLayout {
+    fn at(&self, cell: IVec2) -> Option<usize> {
+        self.furniture
+            .iter()
+            .position(|furniture| furniture.cell == cell)
+    }
+}
+
+#[derive(Resource, Default)]
+struct PlacementMode {
+    active: bool,
+    // inventory slot of the item that gets placed
+    slot: usize,
+    rotation: u8,
+    // the cell in front of the player, None while it's the one the player stands on
+    cell: Option<IVec2>,
+}
+
+// shared by every piece of furniture and the placement preview
+#[derive(Resource)]
+struct FurnitureAssets {
+    mesh: Handle<Mesh>,
+    preview_material: Handle<StandardMaterial>,
+}
+
+impl FromWorld for FurnitureAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Cuboid::from_size(FURNITURE_SIZE));
+        let preview_material =
+            world
+                .resource_mut::<Assets<StandardMaterial>>()
+                .add(StandardMaterial {
+                    base_color: Color::srgba(1.0, 1.0, 1.0, 0.4),
+                    alpha_mode: AlphaMode::Blend,
+                    ..default()
+                });
+        Self {
+            mesh,
+            preview_material,
+        }
+    }
+}
+
+#[derive(Component)]
+struct Furniture {
+    cell: IVec2,
+}
+
+#[derive(Component)]
+struct PlacementPreview;
+
+#[derive(Event)]
+struct PlaceFurniture {
+    slot: usize,
+    cell: IVec2,
+    rotation: u8,
+}
+
+#[derive(Event)]
+struct PickUpFurniture(IVec2);
+
+// the first slot holding an item, starting at `from` and wrapping around
+fn next_item_slot(inventory: &PlayerInventory, from: usize) -> usize {
+    let len = inventory.slots.len().max(1);
+    (0..len)
+        .map(|offset| (from + offset) % len)
+        .find(|slot| inventory.slots[*slot].is_some())
+        .unwrap_or(from)
+}
+
+fn cell_at(position: Vec3, settings: &DecorationSettings) -> IVec2 {
+    (position.xz() / settings.grid_size).round().as_ivec2()
+}
+
+fn furniture_transform(cell: IVec2, rotation: u8, settings: &DecorationSettings) -> Transform {
+    let position = cell.as_vec2() * settings.grid_size;
+    Transform::from_xyz(position.x, FURNITURE_SIZE.y / 2.0, position.y)
+        .with_rotation(Quat::from_rotation_y(rotation as f32 * FRAC_PI_2))
+}
+
+// where furniture can go, entirely on the shell floor and clear of everything standing there
+#[derive(SystemParam)]
+struct FurniturePlacement<'w, 's> {
+    settings: Res<'w, DecorationSettings>,
+    spatial_query: SpatialQuery<'w, 's>,
+    floors: Query<
+        'w,
+        's,
+        (
+            &'static ColliderAabb,
+            &'static CollisionLayers,
+            &'static WorldId,
+        ),
+    >,
+}
+
+impl FurniturePlacement<'_, '_> {
+    fn fits(&self, cell: IVec2, rotation: u8) -> bool {
+        let transform = furniture_transform(cell, rotation, &self.settings);
+        let half_size = (transform.rotation * FURNITURE_SIZE / 2.0).abs();
+        let footprint = Rect::from_center_half_size(transform.translation.xz(), half_size.xz());
+        let on_floor = self.floors.iter().any(|(aabb, layers, world)| {
+            let floor = Rect::from_corners(aabb.min.xz(), aabb.max.xz());
+            *world == WorldId::SHELL
+                && layers.memberships.has_all(GameLayer::Floor)
+                && floor.contains(footprint.min)
+                && floor.contains(footprint.max)
+        });
+        if !on_floor {
+            return false;
+        }
+
+        let shape = Collider::cuboid(
+            FURNITURE_SIZE.x - PLACEMENT_MARGIN,
+            FURNITURE_SIZE.y - PLACEMENT_MARGIN,
+            FURNITURE_SIZE.z - PLACEMENT_MARGIN,
+        );
+        // sensors count too, furniture on a door would block the way out
+        let filter = SpatialQueryFilter::from_mask([GameLayer::Default, GameLayer::Sensor]);
+        self.spatial_query
+            .shape_intersections(&shape, transform.translation, transform.rotation, &filter)
+            .is_empty()
+    }
+}
+
+fn placement_input(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
+    mut mode: ResMut<PlacementMode>,
+    inventory: Res<PlayerInventory>,
+    settings: Res<DecorationSettings>,
+    player: Single<(&Transform, &AimDirection), With<Player>>,
+) {
+    if keys.just_pressed(keybinds.decorate) {
+        mode.active = !mode.active;
+        mode.slot = next_item_slot(&inventory, mode.slot);
+    }
+    if !mode.active {
+        return;
+    }
+
+    let (transform, aim_direction) = *player;
+    let player_cell = cell_at(transform.translation, &settings);
+    let cell = cell_at(
+        transform.translation + aim_direction.0 * settings.reach,
+        &settings,
+    );
+    mode.cell = (cell != player_cell).then_some(cell);
+
+    if keys.just_pressed(keybinds.cycle_furniture) {
+        mode.slot = next_item_slot(&inventory, mode.slot + 1);
+    }
+    if keys.just_pressed(keybinds.rotate_furniture) {
+        mode.rotation = (mode.rotation + 1) % 4;
+    }
+    let Some(cell) = mode.cell else {
+        return;
+    };
+    if keys.just_pressed(keybinds.interact) {
+        commands.trigger(PlaceFurniture {
+            slot: mode.slot,
+            cell,
+            rotation: mode.rotation,
+        });
+    } else if keys.just_pressed(keybinds.pick_up_furniture) {
+        commands.trigger(PickUpFurniture(cell));
+    }
+}
+
+fn update_placement_preview(
+    mut commands: Commands,
+    mode: Res<PlacementMode>,
+    inventory: Res<PlayerInventory>,
+    layout: Res<ShellLayout>,
+    placement: FurniturePlacement,
+    mut preview: Query<(Entity, &mut Transform, &mut Visibility), With<PlacementPreview>>,
+    furniture_assets: Res<FurnitureAssets>,
+) {
+    if !mode.active {
+        for (entity, ..) in &preview {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+    let Ok((_, mut transform, mut visibility)) = preview.single_mut() else {
+        commands.spawn((
+            PlacementPreview,
+            Mesh3d(furniture_assets.mesh.clone()),
+            MeshMaterial3d(furniture_assets.preview_material.clone()),
+            Transform::default(),
+            Visibility::Hidden,
+            WorldId::SHELL,
+        ));
+        return;
+    };
+
+    let has_item = inventory
+        .slots
+        .get(mode.slot)
+        .is_some_and(|slot| slot.is_some());
+    match mode.cell {
+        Some(cell)
+            if has_item && layout.at(cell).is_none() && placement.fits(cell, mode.rotation) =>
+        {
+            *transform = furniture_transform(cell, mode.rotation, &placement.settings);
+            *visibility = Visibility::Visible;
+        }
+        _ => *visibility = Visibility::Hidden,
+    }
+}
+
+fn spawn_furniture(
+    commands: &mut Commands,
+    furniture_assets: &FurnitureAssets,
+    materials: &mut Assets<StandardMaterial>,
+    furniture: &PlacedFurniture,
+    settings: &DecorationSettings,
+) {
+    commands.spawn((
+        Mesh3d(furniture_assets.mesh.clone()),
+        MeshMaterial3d(materials.add(item_color(&furniture.item))),
+        furniture_transform(furniture.cell, furniture.rotation, settings),
+        Collider::cuboid(FURNITURE_SIZE.x, FURNITURE_SIZE.y, FURNITURE_SIZE.z),
+        RigidBody::Static,
+        Furniture {
+            cell: furniture.cell,
+        },
+        Name::new(furniture.item.clone()),
+        WorldId::SHELL,
+    ));
+}
+
+fn on_place_furniture(
+    event: On<PlaceFurniture>,
+    mut commands: Commands,
+    mut layout: ResMut<ShellLayout>,
+    mut inventory: ResMut<PlayerInventory>,
+    placement: FurniturePlacement,
+    furniture_assets: Res<FurnitureAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if layout.at(event.cell).is_some() || !placement.fits(event.cell, event.rotation) {
+        return;
+    }
+    let Some(item) = inventory
+        .slots
+        .get_mut(event.slot)
+        .and_then(|slot| slot.take())
+    else {
+        return;
+    };
+    let furniture = PlacedFurniture {
+        item,
+        cell: event.cell,
+        rotation: event.rotation,
+    };
+    spawn_furniture(
+        &mut commands,
+        &furniture_assets,
+        &mut materials,
+        &furniture,
+        &placement.settings,
+    );
+    layout.furniture.push(furniture);
+}
+
+fn on_pick_up_furniture(
+    event: On<PickUpFurniture>,
+    mut commands: Commands,
+    mut layout: ResMut<ShellLayout>,
+    mut inventory: ResMut<PlayerInventory>,
+    furniture_query: Query<(Entity, &Furniture)>,
+) {
+    let Some(index) = layout.at(event.0) else {
+        return;
+    };
+    let Some(free_slot) = inventory.slots.iter_mut().find(|slot| slot.is_none()) else {
+        warn!("no free inventory slot to pick up furniture");
+        return;
+    };
+    *free_slot = Some(layout.furniture.remove(index).item);
+    for (entity, furniture) in &furniture_query {
+        if furniture.cell == event.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn on_shell_spawned(
+    event: On<WorldSpawned>,
+    mut commands: Commands,
+    layout: Res<ShellLayout>,
+    settings: Res<DecorationSettings>,
+    furniture_assets: Res<FurnitureAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if event.0 != WorldId::SHELL {
+        return;
+    }
+    for furniture in &layout.furniture {
+        spawn_furniture(
+            &mut commands,
+            &furniture_assets,
+            &mut materials,
+            furniture,
+            &settings,
+        );
+    }
+}
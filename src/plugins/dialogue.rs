@@ -0,0 +1,619 @@
+use bevy::{asset::LoadedFolder, prelude::*};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::components::gamestate::ActivityState;
+use crate::components::player::Player;
+use crate::plugins::cutscene::CutsceneFlags;
+use crate::plugins::input::KeyBindings;
+use crate::plugins::inventory::PlayerInventory;
+use crate::ron_asset::{RonAsset, RonAssetLoader};
+
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Dialogue>()
+            .init_asset_loader::<RonAssetLoader<Dialogue>>()
+            .init_resource::<DialogueSettings>()
+            .register_type::<DialogueSettings>()
+            .add_systems(Startup, load_dialogue_library)
+            .add_systems(
+                Update,
+                // after the dialogue systems so the key press that starts talking doesn't also
+                // advance the first line
+                talk_to_npc
+                    .after(reveal_dialogue_text)
+                    .run_if(in_state(ActivityState::Playing)),
+            )
+            .add_systems(
+                Update,
+                (advance_dialogue, show_dialogue_line, reveal_dialogue_text)
+                    .chain()
+                    // closing the dialogue removes the resource halfway through the chain
+                    .distributive_run_if(resource_exists::<ActiveDialogue>)
+                    .run_if(not(in_state(ActivityState::Pause))),
+            )
+            .add_observer(on_start_dialogue)
+            .add_observer(on_close_dialogue);
+    }
+}
+
+const DIALOGUE_FOLDER: &str = "dialogue";
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct DialogueSettings {
+    pub chars_per_second: f32,
+    // how close the player has to be to start talking to an npc
+    pub talk_range: f32,
+}
+
+impl Default for DialogueSettings {
+    fn default() -> Self {
+        Self {
+            chars_per_second: 40.0,
+            talk_range: 1.5,
+        }
+    }
+}
+
+// a conversation loaded from assets/dialogue/<name>.dialogue.ron, starts at the `start` node
+#[derive(Asset, TypePath, Deserialize)]
+pub struct Dialogue {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+impl RonAsset for Dialogue {
+    const EXTENSION: &'static str = "dialogue.ron";
+}
+
+// lines whose condition fails are left out, after the last line the player picks one of
+// the choices or the dialogue continues at `next`, ending if there is neither, choices are
+// shown on their own when every line is left out
+#[derive(Deserialize)]
+pub struct DialogueNode {
+    pub lines: Vec<DialogueLine>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueLine {
+    pub speaker: String,
+    pub text: String,
+    // image path relative to the assets folder
+    #[serde(default)]
+    pub portrait: Option<String>,
+    #[serde(default)]
+    pub condition: Option<DialogueCondition>,
+}
+
+#[derive(Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub condition: Option<DialogueCondition>,
+    // flag set when the choice is picked, shared with cutscenes
+    #[serde(default)]
+    pub set_flag: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub enum DialogueCondition {
+    HasItem(String),
+    Flag(String),
+    Not(Box<DialogueCondition>),
+}
+
+impl DialogueCondition {
+    fn holds(&self, inventory: &PlayerInventory, flags: &CutsceneFlags) -> bool {
+        match self {
+            DialogueCondition::HasItem(item) => {
+                inventory.slots.iter().flatten().any(|slot| slot == item)
+            }
+            DialogueCondition::Flag(flag) => flags.0.contains(flag),
+            DialogueCondition::Not(condition) => !condition.holds(inventory, flags),
+        }
+    }
+}
+
+fn passes(
+    condition: &Option<DialogueCondition>,
+    inventory: &PlayerInventory,
+    flags: &CutsceneFlags,
+) -> bool {
+    condition
+        .as_ref()
+        .is_none_or(|condition| condition.holds(inventory, flags))
+}
+
+// keeps every dialogue loaded so npcs can start talking right away
+#[derive(Resource)]
+struct DialogueLibrary {
+    _folder: Handle<LoadedFolder>,
+}
+
+fn load_dialogue_library(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(DialogueLibrary {
+        _folder: asset_server.load_folder(DIALOGUE_FOLDER),
+    });
+}
+
+// npc the player can talk to with the interact key
+#[derive(Component)]
+pub struct Npc {
+    pub dialogue: String,
+}
+
+#[derive(Event)]
+pub struct StartDialogue(pub String);
+
+// ends the running dialogue early, used when a cutscene playing it is skipped
+#[derive(Event)]
+pub struct CloseDialogue;
+
+#[derive(Event)]
+pub struct DialogueEnded(pub String);
+
+// present while a dialogue is on screen
+#[derive(Resource)]
+pub struct ActiveDialogue {
+    name: String,
+    dialogue: Handle<Dialogue>,
+    node: String,
+    line: usize,
+    revealed: f32,
+    selected: usize,
+    // gameplay is resumed when the dialogue wasn't started by a cutscene
+    resume_playing: bool,
+    shown: Option<(String, usize)>,
+}
+
+#[derive(Component)]
+struct DialogueBox;
+
+#[derive(Component)]
+struct DialogueText;
+
+#[derive(Component)]
+struct DialogueChoiceText(usize);
+
+fn talk_to_npc(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
+    settings: Res<DialogueSettings>,
+    player: Single<&Transform, With<Player>>,
+    npcs: Query<(&Npc, &GlobalTransform, &InheritedVisibility)>,
+) {
+    if !keys.just_pressed(keybinds.interact) {
+        return;
+    }
+    let closest = npcs
+        .iter()
+        .filter(|(_, _, visibility)| visibility.get())
+        .map(|(npc, transform, _)| (npc, transform.translation().distance(player.translation)))
+        .filter(|(_, distance)| *distance <= settings.talk_range)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+    if let Some((npc, _)) = closest {
+        commands.trigger(StartDialogue(npc.dialogue.clone()));
+    }
+}
+
+fn on_start_dialogue(
+    event: On<StartDialogue>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    dialogues: Res<Assets<Dialogue>>,
+    active: Option<Res<ActiveDialogue>>,
+    current_activity: Res<State<ActivityState>>,
+    mut next_activity: ResMut<NextState<ActivityState>>,
+) {
+    if active.is_some() {
+        warn!("dialogue {} started while another one is open", event.0);
+        return;
+    }
+    let handle: Handle<Dialogue> =
+        asset_server.load(format!("{DIALOGUE_FOLDER}/{}.dialogue.ron", event.0));
+    let Some(dialogue) = dialogues.get(&handle) else {
+        warn!("dialogue {} is not loaded", event.0);
+        return;
+    };
+    let resume_playing = *current_activity == ActivityState::Playing;
+    if resume_playing {
+        next_activity.set(ActivityState::Dialogue);
+    }
+    commands.insert_resource(ActiveDialogue {
+        name: event.0.clone(),
+        node: dialogue.start.clone(),
+        dialogue: handle,
+        line: 0,
+        revealed: 0.0,
+        selected: 0,
+        resume_playing,
+        shown: None,
+    });
+}
+
+fn on_close_dialogue(
+    _event: On<CloseDialogue>,
+    mut commands: Commands,
+    active: Option<Res<ActiveDialogue>>,
+    dialogue_box: Query<Entity, With<DialogueBox>>,
+    mut next_activity: ResMut<NextState<ActivityState>>,
+) {
+    let Some(active) = active else {
+        return;
+    };
+    for entity in &dialogue_box {
+        commands.entity(entity).despawn();
+    }
+    if active.resume_playing {
+        next_activity.set(ActivityState::Playing);
+    }
+    commands.remove_resource::<ActiveDialogue>();
+    commands.trigger(DialogueEnded(active.name.clone()));
+}
+
+// index of the first line at or after `from` whose condition holds
+fn next_visible_line(
+    node: &DialogueNode,
+    from: usize,
+    inventory: &PlayerInventory,
+    flags: &CutsceneFlags,
+) -> Option<usize> {
+    (from..node.lines.len()).find(|&line| passes(&node.lines[line].condition, inventory, flags))
+}
+
+fn visible_choices<'a>(
+    node: &'a DialogueNode,
+    inventory: &PlayerInventory,
+    flags: &CutsceneFlags,
+) -> Vec<&'a DialogueChoice> {
+    node.choices
+        .iter()
+        .filter(|choice| passes(&choice.condition, inventory, flags))
+        .collect()
+}
+
+fn advance_dialogue(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
+    dialogues: Res<Assets<Dialogue>>,
+    inventory: Res<PlayerInventory>,
+    mut flags: ResMut<CutsceneFlags>,
+    mut active: ResMut<ActiveDialogue>,
+) {
+    let Some(node) = dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+    else {
+        warn!("dialogue {} has no node {}", active.name, active.node);
+        commands.trigger(CloseDialogue);
+        return;
+    };
+    // conditions are checked as lines come up, so the current line may need to move forward,
+    // past the last line only the choices are left
+    let line = next_visible_line(node, active.line, &inventory, &flags);
+    active.line = line.unwrap_or(node.lines.len());
+    let is_last =
+        line.is_none_or(|line| next_visible_line(node, line + 1, &inventory, &flags).is_none());
+    let choices = if is_last {
+        visible_choices(node, &inventory, &flags)
+    } else {
+        Vec::new()
+    };
+    if line.is_none() && choices.is_empty() {
+        let next = node.next.clone();
+        finish_node(&mut commands, &mut active, next);
+        return;
+    }
+    let text_len = line.map_or(0.0, |line| node.lines[line].text.chars().count() as f32);
+
+    if !choices.is_empty() && active.revealed >= text_len {
+        if keys.just_pressed(keybinds.dialogue_up) {
+            active.selected = (active.selected + choices.len() - 1) % choices.len();
+        }
+        if keys.just_pressed(keybinds.dialogue_down) {
+            active.selected = (active.selected + 1) % choices.len();
+        }
+    }
+
+    if !keys.just_pressed(keybinds.interact) {
+        return;
+    }
+    if active.revealed < text_len {
+        active.revealed = text_len;
+    } else if let Some(choice) = choices.get(active.selected.min(choices.len().saturating_sub(1))) {
+        if let Some(flag) = &choice.set_flag {
+            flags.0.insert(flag.clone());
+        }
+        let next = choice.next.clone();
+        finish_node(&mut commands, &mut active, next);
+    } else if is_last {
+        let next = node.next.clone();
+        finish_node(&mut commands, &mut active, next);
+    } else {
+        active.line += 1;
+        active.revealed = 0.0;
+    }
+}
+
+fn finish_node(commands: &mut Commands, active: &mut ActiveDialogue, next: Option<String>) {
+    match next {
+        Some(next) => {
+            active.node = next;
+            active.line = 0;
+            active.revealed = 0.0;
+            active.selected = 0;
+        }
+        None => commands.trigger(CloseDialogue),
+    }
+}
+
+// rebuilds the text box whenever a new line comes up
+fn show_dialogue_line(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    dialogues: Res<Assets<Dialogue>>,
+    inventory: Res<PlayerInventory>,
+    flags: Res<CutsceneFlags>,
+    mut active: ResMut<ActiveDialogue>,
+    dialogue_box: Query<Entity, With<DialogueBox>>,
+) {
+    let current = (active.node.clone(), active.line);
+    if active.shown.as_ref() == Some(&current) {
+        return;
+    }
+    let Some(node) = dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+    else {
+        return;
+    };
+    // None once only the choices are left
+    let line = node.lines.get(active.line);
+    for entity in &dialogue_box {
+        commands.entity(entity).despawn();
+    }
+    let is_last = next_visible_line(node, active.line + 1, &inventory, &flags).is_none();
+    let choices = if is_last {
+        visible_choices(node, &inventory, &flags)
+    } else {
+        Vec::new()
+    };
+    let portrait = line
+        .and_then(|line| line.portrait.as_ref())
+        .map(|path| asset_server.load::<Image>(path.clone()));
+    spawn_dialogue_box(&mut commands, line, portrait, &choices);
+    active.shown = Some(current);
+}
+
+fn spawn_dialogue_box(
+    commands: &mut Commands,
+    line: Option<&DialogueLine>,
+    portrait: Option<Handle<Image>>,
+    choices: &[&DialogueChoice],
+) {
+    commands
+        .spawn((
+            DialogueBox,
+            Node {
+                width: Val::Percent(60.0),
+                position_type: PositionType::Absolute,
+                left: Val::Percent(20.0),
+                bottom: Val::Px(40.0),
+                padding: UiRect::all(Val::Px(15.0)),
+                column_gap: Val::Px(15.0),
+                ..default()
+            },
+            BackgroundColor((Srgba::new(0.15, 0.15, 0.15, 0.9)).into()),
+            ZIndex(998),
+        ))
+        .with_children(|text_box| {
+            if let Some(portrait) = portrait {
+                text_box.spawn((
+                    ImageNode::new(portrait),
+                    Node {
+                        width: Val::Px(96.0),
+                        height: Val::Px(96.0),
+                        flex_shrink: 0.0,
+                        ..default()
+                    },
+                ));
+            }
+            text_box
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    flex_grow: 1.0,
+                    ..default()
+                })
+                .with_children(|column| {
+                    if let Some(line) = line {
+                        column.spawn((
+                            Text::new(line.speaker.clone()),
+                            TextFont {
+                                font_size: 20.0,
+                                ..default()
+                            },
+                            TextColor(Color::srgb(0.0, 0.4, 0.8)),
+                        ));
+                        column.spawn((
+                            DialogueText,
+                            Text::default(),
+                            TextFont {
+                                font_size: 18.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                        ));
+                    }
+                    for (index, choice) in choices.iter().enumerate() {
+                        column.spawn((
+                            DialogueChoiceText(index),
+                            Text::new(choice.text.clone()),
+                            TextFont {
+                                font_size: 18.0,
+                                ..default()
+                            },
+                            TextColor(Color::WHITE),
+                            Visibility::Hidden,
+                        ));
+                    }
+                });
+        });
+}
+
+// typewriter reveal, choices only show up once the whole line is out
+fn reveal_dialogue_text(
+    time: Res<Time>,
+    settings: Res<DialogueSettings>,
+    mut active: ResMut<ActiveDialogue>,
+    dialogues: Res<Assets<Dialogue>>,
+    mut text: Query<&mut Text, With<DialogueText>>,
+    mut choices: Query<(&DialogueChoiceText, &mut TextColor, &mut Visibility)>,
+) {
+    let Some(node) = dialogues
+        .get(&active.dialogue)
+        .and_then(|dialogue| dialogue.nodes.get(&active.node))
+    else {
+        return;
+    };
+    // with only the choices left there's no text to reveal
+    let line = node.lines.get(active.line);
+    let text_len = line.map_or(0, |line| line.text.chars().count());
+    active.revealed =
+        (active.revealed + settings.chars_per_second * time.delta_secs()).min(text_len as f32);
+    if let Some(line) = line {
+        for mut text in &mut text {
+            text.0 = line.text.chars().take(active.revealed as usize).collect();
+        }
+    }
+    let fully_revealed = active.revealed as usize >= text_len;
+    for (choice, mut color, mut visibility) in &mut choices {
+        *visibility = if fully_revealed {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        color.0 = if choice.0 == active.selected {
+            Color::srgb(1.0, 0.8, 0.2)
+        } else {
+            Color::WHITE
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    fn dialogue_app(flags: &[&str]) -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin, AssetPlugin::default()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )))
+            .insert_state(ActivityState::Dialogue)
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<KeyBindings>()
+            .init_resource::<PlayerInventory>()
+            .insert_resource(CutsceneFlags(
+                flags.iter().map(|flag| flag.to_string()).collect(),
+            ))
+            .add_plugins(DialoguePlugin);
+        app
+    }
+
+    fn start(app: &mut App, source: &str) {
+        let dialogue: Dialogue = ron::from_str(source).unwrap();
+        let node = dialogue.start.clone();
+        let handle = app
+            .world_mut()
+            .resource_mut::<Assets<Dialogue>>()
+            .add(dialogue);
+        app.insert_resource(ActiveDialogue {
+            name: "test".into(),
+            dialogue: handle,
+            node,
+            line: 0,
+            revealed: 0.0,
+            selected: 0,
+            resume_playing: false,
+            shown: None,
+        });
+    }
+
+    fn press_interact(app: &mut App) {
+        let interact = app.world().resource::<KeyBindings>().interact;
+        let mut keys = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        keys.press(interact);
+        app.update();
+        let mut keys = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+        keys.release(interact);
+        keys.clear();
+    }
+
+    fn shown_choices(app: &mut App) -> Vec<String> {
+        app.world_mut()
+            .query_filtered::<(&Text, &Visibility), With<DialogueChoiceText>>()
+            .iter(app.world())
+            .filter(|(_, visibility)| **visibility != Visibility::Hidden)
+            .map(|(text, _)| text.0.clone())
+            .collect()
+    }
+
+    #[test]
+    fn returning_players_skip_the_greeting() {
+        let mut app = dialogue_app(&["met_hermit"]);
+        start(
+            &mut app,
+            include_str!("../../assets/dialogue/hermit.dialogue.ron"),
+        );
+        // the first update has no time passing, the next reveals 4 characters each
+        for _ in 0..4 {
+            app.update();
+        }
+        assert_eq!(app.world().resource::<ActiveDialogue>().line, 1);
+        let mut texts = app
+            .world_mut()
+            .query_filtered::<&Text, With<DialogueText>>();
+        let text = texts.single(app.world()).unwrap();
+        assert!(text.0.starts_with("Back again?"), "{}", text.0);
+    }
+
+    #[test]
+    fn a_node_without_visible_lines_still_offers_its_choices() {
+        let mut app = dialogue_app(&[]);
+        start(
+            &mut app,
+            r#"(
+                start: "ask",
+                nodes: {
+                    "ask": (
+                        lines: [(speaker: "Hermit", text: "Hm?", condition: Some(Flag("asked")))],
+                        choices: [(text: "Tell me more.", next: Some("more")), (text: "Bye.")],
+                    ),
+                    "more": (lines: [(speaker: "Hermit", text: "Not much to tell.")]),
+                },
+            )"#,
+        );
+        app.update();
+        assert_eq!(shown_choices(&mut app), ["Tell me more.", "Bye."]);
+
+        press_interact(&mut app);
+        let active = app.world().resource::<ActiveDialogue>();
+        assert_eq!(active.node, "more");
+        assert_eq!(active.line, 0);
+    }
+}
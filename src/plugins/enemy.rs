@@ -0,0 +1,376 @@
+use avian3d::prelude::*;
+use bevy::{
+    asset::{LoadState, LoadedFolder},
+    ecs::system::EntityCommands,
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    components::{
+        enemy::{Enemy, EnemyKind},
+        gamestate::ActivityState,
+        health::{Health, Invulnerable},
+        player::Player,
+        world::WorldId,
+    },
+    plugins::{camera::AddTrauma, player::PlayerDied},
+    ron_asset::{RonAsset, RonAssetLoader},
+};
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<EnemyArchetype>()
+            .init_asset_loader::<RonAssetLoader<EnemyArchetype>>()
+            .init_resource::<EnemyConfig>()
+            .register_type::<EnemyConfig>()
+            .add_systems(Startup, load_enemy_library)
+            .add_systems(Update, build_enemies)
+            .add_systems(
+                Update,
+                (move_enemies, enemy_attacks).run_if(in_state(ActivityState::Playing)),
+            )
+            .add_observer(on_enemy_attack);
+    }
+}
+
+const ENEMY_FOLDER: &str = "enemies";
+
+// one kind of enemy, loaded from assets/enemies/<name>.enemy.ron
+#[derive(Asset, TypePath, Deserialize)]
+pub struct EnemyArchetype {
+    pub model: EnemyModel,
+    pub collider: EnemyShape,
+    pub health: u16,
+    pub speed: f32,
+    pub behavior: EnemyBehavior,
+    #[serde(default)]
+    pub weapon: Option<EnemyWeaponDefinition>,
+    // name of the loot table rolled when the enemy dies
+    #[serde(default)]
+    pub loot_table: Option<String>,
+}
+
+impl RonAsset for EnemyArchetype {
+    const EXTENSION: &'static str = "enemy.ron";
+}
+
+#[derive(Deserialize)]
+pub enum EnemyModel {
+    // a mesh in the shape of the collider
+    Shape { color: Color },
+    // a glTF scene path, e.g. "turtle/Turtle.gltf#Scene0"
+    Scene(String),
+}
+
+#[derive(Deserialize)]
+pub enum EnemyShape {
+    Cuboid(Vec3),
+    Sphere(f32),
+    Capsule { radius: f32, length: f32 },
+}
+
+impl EnemyShape {
+    fn collider(&self) -> Collider {
+        match *self {
+            EnemyShape::Cuboid(size) => Collider::cuboid(size.x, size.y, size.z),
+            EnemyShape::Sphere(radius) => Collider::sphere(radius),
+            EnemyShape::Capsule { radius, length } => Collider::capsule(radius, length),
+        }
+    }
+
+    fn mesh(&self) -> Mesh {
+        match *self {
+            EnemyShape::Cuboid(size) => Cuboid::from_size(size).into(),
+            EnemyShape::Sphere(radius) => Sphere::new(radius).into(),
+            EnemyShape::Capsule { radius, length } => Capsule3d::new(radius, length).into(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub enum EnemyBehavior {
+    // stands where it was spawned
+    Idle,
+    // walks straight at the player while they're within sight_range
+    Chase { sight_range: f32 },
+    // walks between points relative to where it was spawned, in order
+    Patrol { points: Vec<Vec3> },
+}
+
+#[derive(Deserialize)]
+pub struct EnemyWeaponDefinition {
+    pub damage: u16,
+    pub range: f32,
+    // seconds between attacks
+    pub cooldown: f32,
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct EnemyConfig {
+    // camera shake when an enemy hits the player
+    hit_trauma: f32,
+    // how close a patrolling enemy has to get to a point before heading to the next
+    patrol_tolerance: f32,
+}
+
+impl Default for EnemyConfig {
+    fn default() -> Self {
+        Self {
+            hit_trauma: 0.4,
+            patrol_tolerance: 0.2,
+        }
+    }
+}
+
+#[derive(Component)]
+struct EnemyMovement {
+    speed: f32,
+    behavior: EnemyBehavior,
+    home: Vec3,
+    patrol_index: usize,
+}
+
+#[derive(Component)]
+struct EnemyWeapon {
+    damage: u16,
+    range: f32,
+    cooldown: Timer,
+}
+
+// the loot table rolled when this enemy dies
+#[derive(Component)]
+pub struct EnemyLoot(pub String);
+
+#[derive(Resource)]
+struct EnemyLibrary {
+    _folder: Handle<LoadedFolder>,
+}
+
+fn load_enemy_library(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(EnemyLibrary {
+        _folder: asset_server.load_folder(ENEMY_FOLDER),
+    });
+}
+
+// spawns an enemy of the named archetype, it's filled in once the archetype has loaded so
+// components inserted on the returned entity (e.g. a saved Health) take precedence
+pub fn spawn_enemy<'a>(
+    commands: &'a mut Commands,
+    archetype: &str,
+    transform: Transform,
+    world: WorldId,
+) -> EntityCommands<'a> {
+    commands.spawn((
+        EnemyKind(archetype.to_owned()),
+        transform,
+        Name::new(archetype.to_owned()),
+        world,
+    ))
+}
+
+fn build_enemies(
+    mut commands: Commands,
+    pending: Query<(Entity, &EnemyKind, &Transform), Without<Enemy>>,
+    archetypes: Res<Assets<EnemyArchetype>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, kind, transform) in &pending {
+        let handle = asset_server.load(format!("{ENEMY_FOLDER}/{}.enemy.ron", kind.0));
+        let Some(archetype) = archetypes.get(&handle) else {
+            if let LoadState::Failed(err) = asset_server.load_state(&handle) {
+                error!("could not load enemy archetype {}: {err}", kind.0);
+                commands.entity(entity).despawn();
+            }
+            continue;
+        };
+
+        let mut enemy = commands.entity(entity);
+        enemy.insert((
+            Enemy,
+            archetype.collider.collider(),
+            RigidBody::Dynamic,
+            TransformInterpolation,
+            LinearDamping(0.9),
+            AngularDamping(0.9),
+            EnemyMovement {
+                speed: archetype.speed,
+                behavior: archetype.behavior.clone(),
+                home: transform.translation,
+                patrol_index: 0,
+            },
+        ));
+        enemy.insert_if_new(Health(archetype.health));
+        match &archetype.model {
+            EnemyModel::Shape { color } => {
+                enemy.insert((
+                    Mesh3d(meshes.add(archetype.collider.mesh())),
+                    MeshMaterial3d(materials.add(*color)),
+                ));
+            }
+            EnemyModel::Scene(path) => {
+                enemy.insert(SceneRoot(asset_server.load(path.clone())));
+            }
+        }
+        if archetype.speed > 0.0 {
+            // walking enemies stay upright, idle ones still tumble when shot
+            enemy.insert(LockedAxes::ROTATION_LOCKED);
+        }
+        if let Some(weapon) = &archetype.weapon {
+            enemy.insert(EnemyWeapon {
+                damage: weapon.damage,
+                range: weapon.range,
+                cooldown: Timer::from_seconds(weapon.cooldown, TimerMode::Once),
+            });
+        }
+        if let Some(loot_table) = &archetype.loot_table {
+            enemy.insert(EnemyLoot(loot_table.clone()));
+        }
+    }
+}
+
+fn move_enemies(
+    config: Res<EnemyConfig>,
+    player: Single<&Transform, With<Player>>,
+    mut enemies: Query<(&Transform, &mut EnemyMovement, &mut LinearVelocity), Without<Player>>,
+) {
+    for (transform, mut movement, mut velocity) in &mut enemies {
+        let target = match &movement.behavior {
+            EnemyBehavior::Idle => continue,
+            EnemyBehavior::Chase { sight_range } => {
+                if transform.translation.distance(player.translation) > *sight_range {
+                    continue;
+                }
+                player.translation
+            }
+            EnemyBehavior::Patrol { points } => {
+                let Some(point) = points.get(movement.patrol_index) else {
+                    continue;
+                };
+                let target = movement.home + *point;
+                if transform.translation.xz().distance(target.xz()) < config.patrol_tolerance {
+                    movement.patrol_index = (movement.patrol_index + 1) % points.len();
+                }
+                target
+            }
+        };
+        // only steer on the ground plane, gravity keeps the vertical velocity
+        let direction = (target - transform.translation).xz().normalize_or_zero();
+        velocity.x = direction.x * movement.speed;
+        velocity.z = direction.y * movement.speed;
+    }
+}
+
+// an enemy ran out of health, triggered right before it's despawned
+#[derive(Event)]
+pub struct EnemyDefeated(pub Entity);
+
+#[derive(Event)]
+struct EnemyAttack {
+    damage: u16,
+}
+
+fn enemy_attacks(
+    mut commands: Commands,
+    time: Res<Time>,
+    player: Single<&Transform, With<Player>>,
+    mut enemies: Query<(&Transform, &mut EnemyWeapon), Without<Player>>,
+) {
+    for (transform, mut weapon) in &mut enemies {
+        weapon.cooldown.tick(time.delta());
+        if weapon.cooldown.is_finished()
+            && transform.translation.distance(player.translation) <= weapon.range
+        {
+            weapon.cooldown.reset();
+            commands.trigger(EnemyAttack {
+                damage: weapon.damage,
+            });
+        }
+    }
+}
+
+fn on_enemy_attack(
+    event: On<EnemyAttack>,
+    mut commands: Commands,
+    config: Res<EnemyConfig>,
+    player: Single<(&mut Health, Has<Invulnerable>), With<Player>>,
+) {
+    let (mut health, invulnerable) = player.into_inner();
+    if invulnerable || health.0 == 0 {
+        return;
+    }
+    health.0 = health.0.saturating_sub(event.damage);
+    commands.trigger(AddTrauma(config.hit_trauma));
+    if health.0 == 0 {
+        commands.trigger(PlayerDied);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Deaths(u32);
+
+    fn attack_app(health: u16) -> App {
+        let mut app = App::new();
+        app.init_resource::<EnemyConfig>()
+            .init_resource::<Deaths>()
+            .add_observer(on_enemy_attack)
+            .add_observer(|_event: On<PlayerDied>, mut deaths: ResMut<Deaths>| deaths.0 += 1);
+        app.world_mut().spawn((Player, Health(health)));
+        app
+    }
+
+    // returns the player's health after the hit
+    fn attack(app: &mut App, damage: u16) -> u16 {
+        app.world_mut().trigger(EnemyAttack { damage });
+        app.world_mut().flush();
+        app.world_mut()
+            .query_filtered::<&Health, With<Player>>()
+            .single(app.world())
+            .unwrap()
+            .0
+    }
+
+    fn deaths(app: &App) -> u32 {
+        app.world().resource::<Deaths>().0
+    }
+
+    #[test]
+    fn attacks_hurt_the_player() {
+        let mut app = attack_app(10);
+        assert_eq!(attack(&mut app, 3), 7);
+        assert_eq!(attack(&mut app, 3), 4);
+        assert_eq!(deaths(&app), 0);
+    }
+
+    #[test]
+    fn invulnerable_players_take_no_damage() {
+        let mut app = attack_app(10);
+        let player = app
+            .world_mut()
+            .query_filtered::<Entity, With<Player>>()
+            .single(app.world())
+            .unwrap();
+        app.world_mut()
+            .entity_mut(player)
+            .insert(Invulnerable(Timer::from_seconds(1.0, TimerMode::Once)));
+        assert_eq!(attack(&mut app, 3), 10);
+    }
+
+    #[test]
+    fn the_killing_blow_triggers_death_once() {
+        let mut app = attack_app(2);
+        assert_eq!(attack(&mut app, 5), 0);
+        assert_eq!(deaths(&app), 1);
+        assert_eq!(attack(&mut app, 5), 0);
+        assert_eq!(deaths(&app), 1);
+    }
+}
@@ -12,6 +12,18 @@ impl Plugin for InputPlugin {
 pub struct KeyBindings {
     pub retract_to_shell: KeyCode,
     pub open_inventory: KeyCode,
+    pub dash: KeyCode,
+    pub cycle_facing_mode: KeyCode,
+    pub skip_cutscene: KeyCode,
+    pub interact: KeyCode,
+    pub dialogue_up: KeyCode,
+    pub dialogue_down: KeyCode,
+    pub decorate: KeyCode,
+    pub cycle_furniture: KeyCode,
+    pub rotate_furniture: KeyCode,
+    pub pick_up_furniture: KeyCode,
+    pub quick_save: KeyCode,
+    pub quick_load: KeyCode,
 }
 
 impl Default for KeyBindings {
@@ -19,6 +31,18 @@ impl Default for KeyBindings {
         Self {
             retract_to_shell: KeyCode::ShiftLeft,
             open_inventory: KeyCode::Tab,
+            dash: KeyCode::Space,
+            cycle_facing_mode: KeyCode::KeyF,
+            skip_cutscene: KeyCode::Escape,
+            interact: KeyCode::KeyE,
+            dialogue_up: KeyCode::ArrowUp,
+            dialogue_down: KeyCode::ArrowDown,
+            decorate: KeyCode::KeyB,
+            cycle_furniture: KeyCode::KeyC,
+            rotate_furniture: KeyCode::KeyR,
+            pick_up_furniture: KeyCode::KeyQ,
+            quick_save: KeyCode::F5,
+            quick_load: KeyCode::F9,
         }
     }
 }
@@ -23,6 +23,14 @@ impl Default for PlayerInventory {
     }
 }
 
+// there are no item models yet, tell items apart by a color picked from their name
+pub fn item_color(item: &str) -> Color {
+    let hue = item.bytes().fold(0u32, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as u32)
+    }) % 360;
+    Color::hsl(hue as f32, 0.5, 0.5)
+}
+
 fn toggle_inventory(
     mut commands: Commands,
     keys: Res<ButtonInput<KeyCode>>,
@@ -1,178 +1,368 @@
 use crate::{
-    components::{enemy::Enemy, gamelayer::GameLayer, health::Health, player::Player},
-    plugins::world_switching::MovedOutside,
+    components::{
+        camera::CameraBounds,
+        gamelayer::GameLayer,
+        health::Health,
+        player::Player,
+        world::{LevelKey, WorldId},
+    },
+    plugins::{
+        cutscene_script::{CutsceneParams, CutsceneTrigger},
+        dialogue::Npc,
+        enemy::spawn_enemy,
+        waves::{SpawnerDefinition, StartArena, spawn_enemy_spawner},
+        world_streaming::SavedWorld,
+        world_switching::{ReturnToCheckpoint, UseDoor, WorldDefinition, WorldRegistry},
+    },
+    ron_asset::{RonAsset, RonAssetLoader},
 };
 use avian3d::prelude::*;
 use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
 
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_level);
+        app.init_asset::<Level>()
+            .init_asset_loader::<RonAssetLoader<Level>>()
+            .add_systems(Startup, register_worlds);
     }
 }
 
-#[derive(Component)]
-pub struct OutsideWorld;
+// everything spawned for one world, loaded from assets/levels
+#[derive(Asset, TypePath, Deserialize)]
+pub struct Level {
+    pub camera_bounds: Rect,
+    #[serde(default)]
+    pub lights: Vec<Vec3>,
+    // where doors and transitions can put the player
+    #[serde(default)]
+    pub spawn_points: HashMap<String, Vec3>,
+    pub props: Vec<Prop>,
+    #[serde(default)]
+    pub enemies: Vec<LevelEnemy>,
+    // only emit enemies while an arena is running
+    #[serde(default)]
+    pub spawners: Vec<SpawnerDefinition>,
+}
 
-#[derive(Component)]
-pub struct InsideWorld;
+impl RonAsset for Level {
+    const EXTENSION: &'static str = "level.ron";
+}
 
-fn spawn_level(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Outside
-    commands.spawn((
-        Mesh3d(meshes.add(Plane3d::default().mesh().size(12.0, 12.0))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Collider::cuboid(12., 0.0, 12.),
-        RigidBody::Static,
-        CollisionLayers::new(GameLayer::Floor, GameLayer::Default),
-        OutsideWorld,
-    ));
+#[derive(Deserialize)]
+pub struct Prop {
+    // named props keep their state while their world is unloaded
+    #[serde(default)]
+    pub name: Option<String>,
+    pub shape: PropShape,
+    pub color: Color,
+    #[serde(default)]
+    pub position: Vec3,
+    // degrees around the Y axis
+    #[serde(default)]
+    pub yaw: f32,
+    pub body: PropBody,
+    #[serde(default)]
+    pub kind: PropKind,
+}
 
-    // Light
-    commands.spawn((
-        PointLight {
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(4.0, 8.0, 4.0),
-        OutsideWorld,
-    ));
+#[derive(Deserialize)]
+pub struct LevelEnemy {
+    // file name in assets/enemies, without the extension
+    pub archetype: String,
+    // named enemies stay dead and keep their health while the world is unloaded
+    #[serde(default)]
+    pub name: Option<String>,
+    pub position: Vec3,
+    // degrees around the Y axis
+    #[serde(default)]
+    pub yaw: f32,
+}
 
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::srgb_u8(0, 255, 0))),
-        Transform::from_xyz(2.5, 10., 0.0), // Y is now height
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Dynamic,
-        TransformInterpolation,
-        LinearDamping(0.9),
-        AngularDamping(0.9),
-        Name::new("Dynamic Cube"),
-        OutsideWorld,
-    ));
+#[derive(Deserialize)]
+pub enum PropShape {
+    // width and depth of a flat floor the player can stand on
+    Floor(f32, f32),
+    Cuboid(Vec3),
+    Capsule { radius: f32, length: f32 },
+}
 
-    // Enemy cube
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::srgb_u8(255, 0, 0))),
-        Transform::from_xyz(0.0, 0.25, 2.5), // Now at positive Z instead of Y
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Dynamic,
-        TransformInterpolation,
-        LinearDamping(0.9),
-        AngularDamping(0.9),
-        Enemy,
-        Health(2),
-        Name::new("Enemy"),
-        OutsideWorld,
-    ));
+#[derive(Deserialize)]
+pub enum PropBody {
+    Static,
+    Dynamic,
+    Sensor,
+}
 
-    // Static cubes
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_xyz(-2.5, 0.25, 0.0),
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        OutsideWorld,
-    ));
+#[derive(Deserialize, Default)]
+pub enum PropKind {
+    #[default]
+    Prop,
+    Npc {
+        dialogue: String,
+    },
+    // leads to the `spawn` point of `world`, coming back puts the player at `return_to` here
+    Door {
+        world: WorldId,
+        spawn: String,
+        return_to: String,
+    },
+    // goes back out through the door the player came in from
+    Exit,
+    // starts the waves of the spawners in this level
+    Arena,
+    // plays the cutscene script when the player walks in, give it a Sensor body
+    CutsceneTrigger {
+        script: String,
+        #[serde(default)]
+        params: CutsceneParams,
+    },
+}
 
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_xyz(-3.0, 0.25, 0.0),
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        OutsideWorld,
-    ));
+fn register_worlds(mut registry: ResMut<WorldRegistry>) {
+    registry.register(
+        WorldId::OUTSIDE,
+        WorldDefinition {
+            name: "Outside".into(),
+            level: "levels/outside.level.ron".into(),
+            combat: true,
+        },
+    );
+    registry.register(
+        WorldId::HUT,
+        WorldDefinition {
+            name: "Hermit's Hut".into(),
+            level: "levels/hut.level.ron".into(),
+            combat: false,
+        },
+    );
+    registry.register(
+        WorldId::SHELL,
+        WorldDefinition {
+            name: "Shell".into(),
+            level: "levels/shell.level.ron".into(),
+            combat: false,
+        },
+    );
+}
 
+// spawns `level` into `world` and returns the keys of the named props that were spawned
+pub fn spawn_level(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    world: &WorldId,
+    level: &Level,
+    saved: Option<&SavedWorld>,
+) -> HashSet<String> {
     commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_xyz(-3.0, 0.25, 0.5),
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        OutsideWorld,
+        CameraBounds(level.camera_bounds),
+        Name::new(format!("{world} Camera Bounds")),
+        world.clone(),
     ));
+    for light in &level.lights {
+        commands.spawn((
+            PointLight {
+                shadows_enabled: true,
+                ..default()
+            },
+            Transform::from_translation(*light),
+            world.clone(),
+        ));
+    }
 
-    // Rotated cube
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::WHITE)),
-        Transform::from_xyz(-3.0, 0.25, 3.0)
-            .with_rotation(Quat::from_rotation_y(45_f32.to_radians())), // Rotate around Y (vertical) axis
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        OutsideWorld,
-    ));
+    let mut spawned = HashSet::new();
+    for prop in &level.props {
+        let mut transform = Transform::from_translation(prop.position)
+            .with_rotation(Quat::from_rotation_y(prop.yaw.to_radians()));
+        let mut velocity = None;
+        let mut health = None;
+        if let Some(name) = &prop.name {
+            if let Some(saved) = saved {
+                if saved.removed.contains(name) {
+                    continue;
+                }
+                if let Some(entity) = saved.entities.get(name) {
+                    transform = entity.transform;
+                    health = entity.health;
+                    velocity = entity.velocity;
+                }
+            }
+            spawned.insert(name.clone());
+        }
 
-    // Inside world
-    commands.spawn((
-        Mesh3d(meshes.add(Plane3d::default().mesh().size(12.0, 12.0))),
-        MeshMaterial3d(materials.add(Color::linear_rgb(14. / 255., 209. / 255., 95. / 255.))),
-        Collider::cuboid(12., 0.0, 12.),
-        RigidBody::Static,
-        CollisionLayers::new(GameLayer::Floor, GameLayer::Default),
-        InsideWorld,
-    ));
+        let (mesh, collider) = match prop.shape {
+            PropShape::Floor(width, depth) => (
+                meshes.add(Plane3d::default().mesh().size(width, depth)),
+                Collider::cuboid(width, 0.0, depth),
+            ),
+            PropShape::Cuboid(size) => (
+                meshes.add(Cuboid::from_size(size)),
+                Collider::cuboid(size.x, size.y, size.z),
+            ),
+            PropShape::Capsule { radius, length } => (
+                meshes.add(Capsule3d::new(radius, length)),
+                Collider::capsule(radius, length),
+            ),
+        };
+        let mut entity = commands.spawn((
+            Mesh3d(mesh),
+            MeshMaterial3d(materials.add(prop.color)),
+            transform,
+            collider,
+            world.clone(),
+        ));
+        if matches!(prop.shape, PropShape::Floor(..)) {
+            entity.insert(CollisionLayers::new(
+                GameLayer::Floor,
+                [GameLayer::Default, GameLayer::Pickup],
+            ));
+        }
+        match prop.body {
+            PropBody::Static => {
+                entity.insert(RigidBody::Static);
+            }
+            PropBody::Dynamic => {
+                entity.insert((
+                    RigidBody::Dynamic,
+                    TransformInterpolation,
+                    LinearDamping(0.9),
+                    AngularDamping(0.9),
+                ));
+                if let Some((linear, angular)) = velocity {
+                    entity.insert((LinearVelocity(linear), AngularVelocity(angular)));
+                }
+            }
+            PropBody::Sensor => {
+                entity.insert((
+                    RigidBody::Static,
+                    Sensor,
+                    CollisionEventsEnabled,
+                    CollisionLayers::new(GameLayer::Sensor, GameLayer::Default),
+                ));
+            }
+        }
+        if let Some(name) = &prop.name {
+            entity.insert((Name::new(name.clone()), LevelKey(name.clone())));
+        }
+        if let Some(health) = health {
+            entity.insert(Health(health));
+        }
+        match &prop.kind {
+            PropKind::Prop => {}
+            PropKind::Npc { dialogue } => {
+                entity.insert(Npc {
+                    dialogue: dialogue.clone(),
+                });
+            }
+            PropKind::Door {
+                world,
+                spawn,
+                return_to,
+            } => {
+                let Some(return_to) = level.spawn_points.get(return_to).copied() else {
+                    warn!("door to {world} returns to missing spawn point {return_to}");
+                    continue;
+                };
+                entity
+                    .insert(Door {
+                        world: world.clone(),
+                        spawn: spawn.clone(),
+                        return_to,
+                    })
+                    .observe(on_player_use_door);
+            }
+            PropKind::Exit => {
+                entity.observe(on_player_exit);
+            }
+            PropKind::Arena => {
+                entity.observe(on_player_start_arena);
+            }
+            PropKind::CutsceneTrigger { script, params } => {
+                entity.insert(CutsceneTrigger {
+                    script: script.clone(),
+                    params: params.clone(),
+                });
+            }
+        }
+    }
 
-    // Light
-    commands.spawn((
-        PointLight {
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(4.0, 8.0, 4.0),
-        InsideWorld,
-    ));
+    for enemy in &level.enemies {
+        let mut transform = Transform::from_translation(enemy.position)
+            .with_rotation(Quat::from_rotation_y(enemy.yaw.to_radians()));
+        let mut saved_entity = None;
+        if let Some(name) = &enemy.name {
+            if saved.is_some_and(|saved| saved.removed.contains(name)) {
+                continue;
+            }
+            saved_entity = saved.and_then(|saved| saved.entities.get(name));
+            if let Some(entity) = saved_entity {
+                transform = entity.transform;
+            }
+            spawned.insert(name.clone());
+        }
 
-    // Inside exit
-    commands
-        .spawn((
-            Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.25))),
-            MeshMaterial3d(materials.add(Color::BLACK)),
-            Transform::from_xyz(0., 0.25, 6.0),
-            Collider::cuboid(0.5, 0.5, 0.25),
-            RigidBody::Static,
-            Sensor,
-            CollisionEventsEnabled,
-            CollisionLayers::new(GameLayer::Sensor, GameLayer::Default),
-            InsideWorld,
-        ))
-        .observe(on_player_exit_inside);
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::linear_rgb(0.2941, 0.2156, 0.1098))),
-        Transform::from_xyz(5., 0.25, 4.0),
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        InsideWorld,
-    ));
-    commands.spawn((
-        Mesh3d(meshes.add(Cuboid::new(0.5, 0.5, 0.5))),
-        MeshMaterial3d(materials.add(Color::linear_rgb(19. / 255., 143. / 255., 65. / 255.))),
-        Transform::from_xyz(-5., 0.25, 4.0),
-        Collider::cuboid(0.5, 0.5, 0.5),
-        RigidBody::Static,
-        InsideWorld,
-    ));
+        let mut entity = spawn_enemy(commands, &enemy.archetype, transform, world.clone());
+        if let Some(name) = &enemy.name {
+            entity.insert((Name::new(name.clone()), LevelKey(name.clone())));
+        }
+        // the archetype fills in the rest without overwriting these
+        if let Some(health) = saved_entity.and_then(|entity| entity.health) {
+            entity.insert(Health(health));
+        }
+        if let Some((linear, angular)) = saved_entity.and_then(|entity| entity.velocity) {
+            entity.insert((LinearVelocity(linear), AngularVelocity(angular)));
+        }
+    }
+    for spawner in &level.spawners {
+        spawn_enemy_spawner(commands, spawner, world.clone());
+    }
+    spawned
+}
+
+#[derive(Component)]
+struct Door {
+    world: WorldId,
+    spawn: String,
+    return_to: Vec3,
 }
 
-fn on_player_exit_inside(
+fn on_player_use_door(
     event: On<CollisionStart>,
+    doors: Query<&Door>,
     player_query: Query<&Player>,
     mut commands: Commands,
 ) {
+    let Ok(door) = doors.get(event.collider1) else {
+        return;
+    };
+    if player_query.contains(event.collider2) {
+        commands.trigger(UseDoor {
+            world: door.world.clone(),
+            spawn: door.spawn.clone(),
+            return_to: door.return_to,
+        });
+    }
+}
+
+fn on_player_exit(event: On<CollisionStart>, player_query: Query<&Player>, mut commands: Commands) {
     let _exit_door = event.collider1;
     let other_entity = event.collider2;
 
     if player_query.contains(other_entity) {
-        commands.trigger(MovedOutside);
+        commands.trigger(ReturnToCheckpoint);
+    }
+}
+
+fn on_player_start_arena(
+    event: On<CollisionStart>,
+    player_query: Query<&Player>,
+    mut commands: Commands,
+) {
+    if player_query.contains(event.collider2) {
+        commands.trigger(StartArena);
     }
 }
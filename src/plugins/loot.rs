@@ -0,0 +1,405 @@
+use avian3d::prelude::*;
+use bevy::{asset::LoadedFolder, ecs::system::EntityCommands, prelude::*};
+use rand::{Rng, SeedableRng, seq::IndexedRandom};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+use crate::{
+    components::{gamelayer::GameLayer, gamestate::ActivityState, player::Player, world::WorldId},
+    plugins::{
+        enemy::{EnemyDefeated, EnemyLoot},
+        inventory::{PlayerInventory, item_color},
+    },
+    ron_asset::{RonAsset, RonAssetLoader},
+};
+
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<LootTable>()
+            .init_asset_loader::<RonAssetLoader<LootTable>>()
+            .init_resource::<LootSettings>()
+            .register_type::<LootSettings>()
+            .init_resource::<LootRng>()
+            .init_resource::<PickupAssets>()
+            .add_systems(Startup, load_loot_library)
+            .add_systems(
+                Update,
+                (collect_pickups, despawn_fallen_pickups).run_if(in_state(ActivityState::Playing)),
+            )
+            .add_observer(on_enemy_defeated)
+            .add_observer(on_drop_items);
+    }
+}
+
+const LOOT_FOLDER: &str = "loot";
+const PICKUP_SIZE: f32 = 0.2;
+
+// what an enemy drops, loaded from assets/loot/<name>.loot.ron
+#[derive(Asset, TypePath, Deserialize)]
+pub struct LootTable {
+    // always dropped
+    #[serde(default)]
+    pub guaranteed: Vec<LootDrop>,
+    // how many times `weighted` is rolled
+    #[serde(default)]
+    pub rolls: u32,
+    #[serde(default)]
+    pub weighted: Vec<WeightedDrop>,
+}
+
+impl RonAsset for LootTable {
+    const EXTENSION: &'static str = "loot.ron";
+}
+
+#[derive(Deserialize)]
+pub struct LootDrop {
+    pub item: String,
+    // inclusive range of how many are dropped
+    #[serde(default = "single")]
+    pub count: (u32, u32),
+}
+
+#[derive(Deserialize)]
+pub struct WeightedDrop {
+    pub weight: u32,
+    // None drops nothing, to give a roll a chance to come up empty
+    pub drop: Option<LootDrop>,
+}
+
+fn single() -> (u32, u32) {
+    (1, 1)
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct LootSettings {
+    // the same seed drops the same loot for the same kills
+    seed: u64,
+    // speed pickups fly off at when they're dropped
+    scatter_speed: f32,
+    // how close the player has to be to collect a pickup
+    pickup_radius: f32,
+    // seconds before a dropped pickup can be collected, so the scatter can be seen
+    pickup_delay: f32,
+    // pickups that fall below this height are gone for good, e.g. off the edge of a floor
+    kill_height: f32,
+}
+
+impl Default for LootSettings {
+    fn default() -> Self {
+        Self {
+            seed: 0x5eed,
+            scatter_speed: 2.5,
+            pickup_radius: 0.6,
+            pickup_delay: 0.5,
+            kill_height: -10.0,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct LootRng(pub ChaCha8Rng);
+
+impl FromWorld for LootRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.resource::<LootSettings>().seed;
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+impl LootTable {
+    // the items of one drop, an item shows up once per dropped copy
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<String> {
+        let mut drops: Vec<&LootDrop> = self.guaranteed.iter().collect();
+        for _ in 0..self.rolls {
+            if let Ok(WeightedDrop {
+                drop: Some(drop), ..
+            }) = self.weighted.choose_weighted(rng, |entry| entry.weight)
+            {
+                drops.push(drop);
+            }
+        }
+        let mut items = Vec::new();
+        for drop in drops {
+            let (min, max) = drop.count;
+            let count = rng.random_range(min..=max.max(min));
+            items.extend(std::iter::repeat_n(drop.item.clone(), count as usize));
+        }
+        items
+    }
+}
+
+#[derive(Resource)]
+struct LootLibrary {
+    _folder: Handle<LoadedFolder>,
+}
+
+fn load_loot_library(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(LootLibrary {
+        _folder: asset_server.load_folder(LOOT_FOLDER),
+    });
+}
+
+#[derive(Resource)]
+pub struct PickupAssets {
+    mesh: Handle<Mesh>,
+}
+
+impl FromWorld for PickupAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world
+            .resource_mut::<Assets<Mesh>>()
+            .add(Cuboid::from_length(PICKUP_SIZE));
+        Self { mesh }
+    }
+}
+
+#[derive(Component)]
+pub struct Pickup {
+    pub item: String,
+    delay: Timer,
+}
+
+// scatters `items` as pickups around `position`
+#[derive(Event)]
+pub struct DropItems {
+    pub items: Vec<String>,
+    pub position: Vec3,
+    pub world: WorldId,
+}
+
+fn on_enemy_defeated(
+    event: On<EnemyDefeated>,
+    mut commands: Commands,
+    enemies: Query<(&EnemyLoot, &Transform, &WorldId)>,
+    tables: Res<Assets<LootTable>>,
+    asset_server: Res<AssetServer>,
+    mut rng: ResMut<LootRng>,
+) {
+    let Ok((loot, transform, world)) = enemies.get(event.0) else {
+        return;
+    };
+    let handle = asset_server.load(format!("{LOOT_FOLDER}/{}.loot.ron", loot.0));
+    let Some(table) = tables.get(&handle) else {
+        warn!("loot table {} isn't loaded", loot.0);
+        return;
+    };
+    commands.trigger(DropItems {
+        items: table.roll(&mut rng.0),
+        position: transform.translation,
+        world: world.clone(),
+    });
+}
+
+// a pickup of `item` at `transform`, it can be collected once `delay` seconds have passed
+pub fn spawn_pickup<'a>(
+    commands: &'a mut Commands,
+    pickup_assets: &PickupAssets,
+    materials: &mut Assets<StandardMaterial>,
+    transform: Transform,
+    item: &str,
+    delay: f32,
+    world: WorldId,
+) -> EntityCommands<'a> {
+    commands.spawn((
+        Mesh3d(pickup_assets.mesh.clone()),
+        MeshMaterial3d(materials.add(item_color(item))),
+        transform,
+        Collider::cuboid(PICKUP_SIZE, PICKUP_SIZE, PICKUP_SIZE),
+        RigidBody::Dynamic,
+        // only lands on floors so pickups never block the player or bullets
+        CollisionLayers::new(GameLayer::Pickup, GameLayer::Floor),
+        Name::new(item.to_owned()),
+        Pickup {
+            item: item.to_owned(),
+            delay: Timer::from_seconds(delay, TimerMode::Once),
+        },
+        world,
+    ))
+}
+
+fn on_drop_items(
+    event: On<DropItems>,
+    mut commands: Commands,
+    settings: Res<LootSettings>,
+    mut rng: ResMut<LootRng>,
+    pickup_assets: Res<PickupAssets>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for item in &event.items {
+        // fly off in a random direction, a little upwards so they hop before landing
+        let angle = rng.0.random_range(0.0..TAU);
+        let scatter = Vec3::new(angle.cos(), 1.0, angle.sin()) * settings.scatter_speed;
+        spawn_pickup(
+            &mut commands,
+            &pickup_assets,
+            &mut materials,
+            Transform::from_translation(event.position),
+            item,
+            settings.pickup_delay,
+            event.world.clone(),
+        )
+        .insert(LinearVelocity(scatter));
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<LootSettings>,
+    mut inventory: ResMut<PlayerInventory>,
+    player: Single<&Transform, With<Player>>,
+    mut pickups: Query<(Entity, &Transform, &mut Pickup), Without<Player>>,
+) {
+    for (entity, transform, mut pickup) in &mut pickups {
+        if !pickup.delay.tick(time.delta()).is_finished()
+            || transform.translation.distance(player.translation) > settings.pickup_radius
+        {
+            continue;
+        }
+        // a full inventory leaves the pickup lying there
+        let Some(free_slot) = inventory.slots.iter_mut().find(|slot| slot.is_none()) else {
+            continue;
+        };
+        *free_slot = Some(pickup.item.clone());
+        commands.entity(entity).despawn();
+    }
+}
+
+fn despawn_fallen_pickups(
+    mut commands: Commands,
+    settings: Res<LootSettings>,
+    pickups: Query<(Entity, &Transform), With<Pickup>>,
+) {
+    for (entity, transform) in &pickups {
+        if transform.translation.y < settings.kill_height {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::level::{Level, spawn_level};
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    fn loot_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            AssetPlugin::default(),
+            TransformPlugin,
+            // avian's collider backend needs the scene spawner
+            bevy::scene::ScenePlugin,
+            PhysicsPlugins::default(),
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .init_state::<ActivityState>()
+        .init_resource::<PlayerInventory>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            1.0 / 60.0,
+        )))
+        .add_plugins(LootPlugin);
+        // physics sets up part of itself in Plugin::finish, which only App::run calls otherwise
+        app.finish();
+        app.cleanup();
+
+        let level: Level =
+            ron::from_str(include_str!("../../assets/levels/hut.level.ron")).expect("hut parses");
+        app.world_mut()
+            .run_system_once(
+                move |mut commands: Commands,
+                      mut meshes: ResMut<Assets<Mesh>>,
+                      mut materials: ResMut<Assets<StandardMaterial>>| {
+                    spawn_level(
+                        &mut commands,
+                        &mut meshes,
+                        &mut materials,
+                        &WorldId::HUT,
+                        &level,
+                        None,
+                    );
+                },
+            )
+            .expect("the hut spawns");
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(-2.0, 0.0, 2.0)));
+        app
+    }
+
+    fn pickups(app: &mut App) -> Vec<Vec3> {
+        app.world_mut()
+            .query_filtered::<&Transform, With<Pickup>>()
+            .iter(app.world())
+            .map(|transform| transform.translation)
+            .collect()
+    }
+
+    fn drop_items(app: &mut App, position: Vec3) {
+        app.world_mut().trigger(DropItems {
+            items: vec!["Stool".into(), "Rug".into()],
+            position,
+            world: WorldId::HUT,
+        });
+        app.world_mut().flush();
+    }
+
+    #[test]
+    fn dropped_items_land_on_the_floor_and_can_be_collected() {
+        let mut app = loot_app();
+        drop_items(&mut app, Vec3::new(0.0, 0.5, 0.0));
+        for _ in 0..180 {
+            app.update();
+        }
+
+        let landed = pickups(&mut app);
+        assert_eq!(landed.len(), 2);
+        for position in &landed {
+            assert!(
+                position.y > -0.1 && position.y < PICKUP_SIZE,
+                "pickup at {position} isn't resting on the floor"
+            );
+        }
+        assert!(
+            app.world()
+                .resource::<PlayerInventory>()
+                .slots
+                .iter()
+                .all(Option::is_none)
+        );
+
+        for position in landed {
+            let mut player = app
+                .world_mut()
+                .query_filtered::<&mut Transform, With<Player>>()
+                .single_mut(app.world_mut())
+                .expect("one player");
+            player.translation = position;
+            app.update();
+        }
+        assert!(pickups(&mut app).is_empty());
+        let inventory = app.world().resource::<PlayerInventory>();
+        let mut items: Vec<_> = inventory.slots.iter().flatten().cloned().collect();
+        items.sort();
+        assert_eq!(items, ["Rug", "Stool"]);
+    }
+
+    #[test]
+    fn pickups_that_fall_off_the_world_are_despawned() {
+        let mut app = loot_app();
+        drop_items(&mut app, Vec3::new(100.0, 0.5, 100.0));
+        app.update();
+        assert_eq!(pickups(&mut app).len(), 2);
+        for _ in 0..240 {
+            app.update();
+        }
+        assert!(pickups(&mut app).is_empty());
+    }
+}
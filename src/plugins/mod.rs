@@ -1,8 +1,16 @@
 pub mod camera;
 pub mod cutscene;
+pub mod cutscene_script;
+pub mod decoration;
+pub mod dialogue;
+pub mod enemy;
 pub mod input;
 pub mod inventory;
 pub mod level;
+pub mod loot;
 pub mod player;
+pub mod save;
 pub mod shooting;
+pub mod waves;
+pub mod world_streaming;
 pub mod world_switching;
@@ -1,7 +1,11 @@
 use crate::components::camera::CameraFocus;
+use crate::components::enemy::Enemy;
 use crate::components::gamelayer::GameLayer;
 use crate::components::gamestate::ActivityState;
-use crate::components::player::{Player, PlayerSet};
+use crate::components::health::{Health, Invulnerable};
+use crate::components::player::{AimDirection, Player, PlayerSet};
+use crate::plugins::input::KeyBindings;
+use crate::plugins::save::{LoadGame, SaveSettings, SaveSlot};
 use avian3d::prelude::*;
 use bevy::prelude::*;
 use core::f32;
@@ -21,8 +25,110 @@ const COLLISION_EPSILON: f32 = f32::EPSILON * 80_000.0;
 // I lean towards keeping it at 2 because values greater than 2 jitter when colliding with sharp colliders
 const MAX_MOVEMENT_ITERATIONS: u8 = 2;
 
-#[derive(Component)]
-struct Speed(f32);
+#[derive(Component, Default)]
+struct Velocity(Vec3);
+
+#[derive(Component, Default)]
+struct Dash {
+    direction: Vec3,
+    active: Option<Timer>,
+    cooldown: Option<Timer>,
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct MovementConfig {
+    max_speed: f32,
+    acceleration: f32,
+    // used when there's no input, higher than acceleration so stopping feels snappy
+    deceleration: f32,
+    // radians per second the velocity can rotate towards the input direction
+    turn_rate: f32,
+    dash_speed: f32,
+    dash_duration: f32,
+    dash_cooldown: f32,
+    dash_invulnerability: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: 3.0,
+            acceleration: 20.0,
+            deceleration: 30.0,
+            turn_rate: 12.0,
+            dash_speed: 9.0,
+            dash_duration: 0.15,
+            dash_cooldown: 0.8,
+            dash_invulnerability: 0.25,
+        }
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct HealthConfig {
+    max_health: u16,
+    // seconds the player can't be hurt after coming back from dying
+    respawn_invulnerability: f32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            max_health: 10,
+            respawn_invulnerability: 2.0,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Copy, PartialEq, Default)]
+pub enum FacingMode {
+    #[default]
+    FaceCursor,
+    FaceMovement,
+    // faces the nearest enemy in range, falls back to the cursor
+    LockOn,
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct FacingConfig {
+    pub mode: FacingMode,
+    // higher values turn faster, around 20 is close to snapping
+    rotation_smoothing: f32,
+    lock_on_range: f32,
+}
+
+impl Default for FacingConfig {
+    fn default() -> Self {
+        Self {
+            mode: FacingMode::default(),
+            rotation_smoothing: 15.0,
+            lock_on_range: 8.0,
+        }
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct PushConfig {
+    // mass used to compare against dynamic bodies, the player itself is kinematic and has none
+    player_mass: f32,
+    push_force: f32,
+    // fraction of the remaining movement kept after pushing, 0.0 stops the player like a wall
+    momentum_retention: f32,
+}
+
+impl Default for PushConfig {
+    fn default() -> Self {
+        Self {
+            player_mass: 1.0,
+            push_force: 1.5,
+            momentum_retention: 0.5,
+        }
+    }
+}
 
 #[derive(Component)]
 pub struct PlayerAnimations {
@@ -39,16 +145,34 @@ pub enum AnimationState {
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, spawn_player).add_systems(
-            Update,
-            move_player
-                .in_set(PlayerSet::Movement)
-                .run_if(in_state(ActivityState::Playing)),
-        );
+        app.init_resource::<PushConfig>()
+            .init_resource::<MovementConfig>()
+            .init_resource::<FacingConfig>()
+            .init_resource::<HealthConfig>()
+            .add_systems(Startup, spawn_player)
+            .add_systems(
+                Update,
+                (
+                    (update_velocity, aim_player, move_player)
+                        .chain()
+                        .in_set(PlayerSet::Movement)
+                        .run_if(in_state(ActivityState::Playing)),
+                    tick_invulnerability,
+                ),
+            )
+            .add_observer(on_player_died)
+            .register_type::<PushConfig>()
+            .register_type::<MovementConfig>()
+            .register_type::<FacingConfig>()
+            .register_type::<HealthConfig>();
     }
 }
 
-fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn spawn_player(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    health_config: Res<HealthConfig>,
+) {
     commands.spawn((
         SceneRoot(asset_server.load("turtle/Turtle.gltf#Scene0")),
         Transform::from_xyz(0.0, 0., 0.).with_scale(Vec3::splat(0.25)),
@@ -59,7 +183,10 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
             Quat::IDENTITY,
             Collider::capsule(1., 3.),
         )]),
-        Speed(3.0),
+        Velocity::default(),
+        Dash::default(),
+        AimDirection(Dir3::NEG_Z),
+        Health(health_config.max_health),
         CameraFocus,
         Name::new("Player"),
         PlayerAnimations {
@@ -70,52 +197,169 @@ fn spawn_player(mut commands: Commands, asset_server: Res<AssetServer>) {
     ));
 }
 
-fn move_player(
+fn update_velocity(
+    mut commands: Commands,
     time: Res<Time>,
     keys: Res<ButtonInput<KeyCode>>,
-    player_query: Single<(&mut Transform, &Speed, Entity, &Collider), With<Player>>,
-    spatial_query: SpatialQuery,
-    mut physics_time: ResMut<Time<Physics>>,
-    windows: Single<&Window>,
-    camera: Single<(&Camera, &GlobalTransform)>,
+    keybinds: Res<KeyBindings>,
+    player_query: Single<(&Transform, &mut Velocity, &mut Dash, Entity), With<Player>>,
+    movement_config: Res<MovementConfig>,
 ) {
-    let (mut player_transform, player_speed, player_entity, player_collider) =
+    let (player_transform, mut player_velocity, mut dash, player_entity) =
         player_query.into_inner();
 
-    // rotate to face mouse
-    if let Some(cursor_pos) = windows.cursor_position() {
-        let (camera, camera_transform) = camera.into_inner();
-
-        if let Ok(ray) = camera.viewport_to_world(camera_transform, cursor_pos) {
-            let player_height = player_transform.translation.y;
-            let t = (player_height - ray.origin.y) / ray.direction.y;
-
-            // If t is negative, the intersection is behind the camera
-            if t >= 0.0 {
-                let point = ray.get_point(t);
-                let to_cursor = Vec2::new(point.x, point.z)
-                    - Vec2::new(
-                        player_transform.translation.x,
-                        player_transform.translation.z,
-                    );
-                // Calculate angle in XY plane
-                let angle = to_cursor.y.atan2(to_cursor.x);
-                // Rotate only around Z axis
-                player_transform.rotation =
-                    Quat::from_rotation_y(-angle - std::f32::consts::FRAC_PI_2);
-            }
-        }
-    }
-
     let move_input = Vec3::new(
         (keys.pressed(KeyCode::KeyD) as i32 - keys.pressed(KeyCode::KeyA) as i32) as f32,
         0.,
         -((keys.pressed(KeyCode::KeyW) as i32 - keys.pressed(KeyCode::KeyS) as i32) as f32),
     );
 
-    if move_input.length_squared() > 0.0 {
-        let mut remaining_distance = player_speed.0 * time.delta_secs();
-        let mut move_dir = move_input.normalize();
+    let delta = time.delta_secs();
+    let dash = &mut *dash;
+
+    if let Some(cooldown) = dash.cooldown.as_mut() {
+        cooldown.tick(time.delta());
+        if cooldown.is_finished() {
+            dash.cooldown = None;
+        }
+    }
+
+    if keys.just_pressed(keybinds.dash) && dash.active.is_none() && dash.cooldown.is_none() {
+        // dash towards the input, or the way the turtle is facing when standing still
+        dash.direction = if move_input.length_squared() > 0.0 {
+            move_input.normalize()
+        } else {
+            *player_transform.forward()
+        };
+        dash.active = Some(Timer::from_seconds(
+            movement_config.dash_duration,
+            TimerMode::Once,
+        ));
+        commands
+            .entity(player_entity)
+            .insert(Invulnerable(Timer::from_seconds(
+                movement_config.dash_invulnerability,
+                TimerMode::Once,
+            )));
+    }
+
+    if let Some(timer) = dash.active.as_mut() {
+        timer.tick(time.delta());
+        player_velocity.0 = dash.direction * movement_config.dash_speed;
+        if timer.is_finished() {
+            dash.active = None;
+            dash.cooldown = Some(Timer::from_seconds(
+                movement_config.dash_cooldown,
+                TimerMode::Once,
+            ));
+            player_velocity.0 = player_velocity
+                .0
+                .clamp_length_max(movement_config.max_speed);
+        }
+    } else if move_input.length_squared() > 0.0 {
+        let desired_velocity = move_input.normalize() * movement_config.max_speed;
+        let current = Vec2::new(player_velocity.0.x, player_velocity.0.z);
+        let desired = Vec2::new(desired_velocity.x, desired_velocity.z);
+        // steer towards the input while keeping speed, reversing is left to the acceleration
+        // so turning around slows the turtle down instead of swinging in a wide arc
+        if current.dot(desired) > 0.0 {
+            let turned = current.rotate_towards(desired, movement_config.turn_rate * delta);
+            player_velocity.0 = Vec3::new(turned.x, 0.0, turned.y);
+        }
+        player_velocity.0 = player_velocity
+            .0
+            .move_towards(desired_velocity, movement_config.acceleration * delta);
+    } else {
+        player_velocity.0 = player_velocity
+            .0
+            .move_towards(Vec3::ZERO, movement_config.deceleration * delta);
+    }
+}
+
+fn aim_player(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
+    player_query: Single<(&mut Transform, &mut AimDirection, &Velocity), With<Player>>,
+    enemies: Query<&Transform, (With<Enemy>, Without<Player>, Without<RigidBodyDisabled>)>,
+    windows: Single<&Window>,
+    camera: Single<(&Camera, &GlobalTransform)>,
+    mut facing_config: ResMut<FacingConfig>,
+) {
+    let (mut player_transform, mut aim_direction, player_velocity) = player_query.into_inner();
+
+    if keys.just_pressed(keybinds.cycle_facing_mode) {
+        facing_config.mode = match facing_config.mode {
+            FacingMode::FaceCursor => FacingMode::FaceMovement,
+            FacingMode::FaceMovement => FacingMode::LockOn,
+            FacingMode::LockOn => FacingMode::FaceCursor,
+        };
+    }
+
+    let (camera, camera_transform) = camera.into_inner();
+    let to_cursor = windows.cursor_position().and_then(|cursor_pos| {
+        let ray = camera
+            .viewport_to_world(camera_transform, cursor_pos)
+            .ok()?;
+        let player_height = player_transform.translation.y;
+        let t = (player_height - ray.origin.y) / ray.direction.y;
+
+        // If t is negative, the intersection is behind the camera
+        if t < 0.0 {
+            return None;
+        }
+        let point = ray.get_point(t);
+        Dir3::new(Vec3::new(
+            point.x - player_transform.translation.x,
+            0.0,
+            point.z - player_transform.translation.z,
+        ))
+        .ok()
+    });
+
+    let target = match facing_config.mode {
+        FacingMode::FaceCursor => to_cursor,
+        // keep the last heading when standing still
+        FacingMode::FaceMovement => Dir3::new(player_velocity.0).ok(),
+        FacingMode::LockOn => enemies
+            .iter()
+            .map(|enemy| enemy.translation - player_transform.translation)
+            .filter(|offset| offset.length() <= facing_config.lock_on_range)
+            .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            .and_then(|offset| Dir3::new(Vec3::new(offset.x, 0.0, offset.z)).ok())
+            .or(to_cursor),
+    };
+
+    if let Some(direction) = target {
+        aim_direction.0 = direction;
+    }
+
+    // Calculate angle in XY plane
+    let angle = aim_direction.0.z.atan2(aim_direction.0.x);
+    // Rotate only around Z axis
+    let target_rotation = Quat::from_rotation_y(-angle - std::f32::consts::FRAC_PI_2);
+    // exponential smoothing so the turning speed doesn't depend on the frame rate
+    let factor = 1.0 - (-facing_config.rotation_smoothing * time.delta_secs()).exp();
+    player_transform.rotation = player_transform.rotation.slerp(target_rotation, factor);
+}
+
+fn move_player(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    player_query: Single<(&mut Transform, &mut Velocity, Entity, &Collider), With<Player>>,
+    spatial_query: SpatialQuery,
+    mut physics_time: ResMut<Time<Physics>>,
+    mut rigid_bodies: Query<(&RigidBody, &mut LinearVelocity, &ComputedMass)>,
+    push_config: Res<PushConfig>,
+) {
+    let (mut player_transform, mut player_velocity, player_entity, player_collider) =
+        player_query.into_inner();
+
+    if player_velocity.0.length_squared() > 0.0 {
+        let player_speed = player_velocity.0.length();
+        let mut remaining_distance = player_speed * time.delta_secs();
+        let mut move_dir = player_velocity.0 / player_speed;
+        let mut excluded_entities = vec![player_entity];
         for _ in 0..MAX_MOVEMENT_ITERATIONS {
             if remaining_distance <= COLLISION_EPSILON {
                 break;
@@ -129,7 +373,7 @@ fn move_player(
                 Dir3::new_unchecked(move_dir),
                 &ShapeCastConfig::from_max_distance(remaining_distance),
                 &SpatialQueryFilter::from_mask(GameLayer::Default)
-                    .with_excluded_entities([player_entity]),
+                    .with_excluded_entities(excluded_entities.iter().copied()),
             );
 
             match shape_hit {
@@ -140,6 +384,26 @@ fn move_player(
                         player_transform.translation += safe_movement;
                         remaining_distance -= safe_distance;
                     }
+
+                    if let Ok((body, mut velocity, mass)) = rigid_bodies.get_mut(hit.entity)
+                        && matches!(body, RigidBody::Dynamic)
+                    {
+                        // lighter bodies get pushed up to the player's speed, heavier ones barely move
+                        let mass_ratio = (push_config.player_mass / mass.value()).min(1.0);
+                        let push_speed = player_speed * push_config.push_force * mass_ratio;
+                        // only top up the velocity along the push so holding against a body doesn't accumulate it
+                        let current_speed = velocity.0.dot(move_dir);
+                        if current_speed < push_speed {
+                            velocity.0 += move_dir * (push_speed - current_speed);
+                        }
+
+                        if push_config.momentum_retention > 0.0 {
+                            // keep walking into the body, the solver pushes it out of the kinematic player
+                            remaining_distance *= push_config.momentum_retention * mass_ratio;
+                            excluded_entities.push(hit.entity);
+                            continue;
+                        }
+                    }
                     let mut horizontal = Vec3::new(hit.normal1.x, 0.0, hit.normal1.z);
                     // clamp as circular colliders can produce tiny normals even on perfectly aligned surfaces
                     if horizontal.x.abs() < COLLISION_EPSILON {
@@ -150,6 +414,12 @@ fn move_player(
                     }
                     let horizontal_normal = horizontal.normalize();
 
+                    // lose the velocity going into the wall so it doesn't build up while blocked
+                    if horizontal_normal.is_finite() {
+                        let into_wall = player_velocity.0.dot(horizontal_normal);
+                        player_velocity.0 -= horizontal_normal * into_wall;
+                    }
+
                     // check if we're moving almost directly into the wall (opposite to normal)
                     if (horizontal_normal + move_dir).length_squared()
                         < COLLISION_EPSILON * COLLISION_EPSILON
@@ -186,3 +456,61 @@ fn move_player(
         }
     }
 }
+
+// the player's health ran out
+#[derive(Event)]
+pub struct PlayerDied;
+
+// back to the autosave if there is one, otherwise the player gets back up where they fell
+fn on_player_died(
+    _event: On<PlayerDied>,
+    mut commands: Commands,
+    health_config: Res<HealthConfig>,
+    save_settings: Res<SaveSettings>,
+    player: Single<(Entity, &mut Health), With<Player>>,
+) {
+    let (entity, mut health) = player.into_inner();
+    health.0 = health_config.max_health;
+    commands
+        .entity(entity)
+        .insert(Invulnerable(Timer::from_seconds(
+            health_config.respawn_invulnerability,
+            TimerMode::Once,
+        )));
+    if SaveSlot::Autosave.exists(&save_settings) {
+        commands.trigger(LoadGame(SaveSlot::Autosave));
+    }
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut query {
+        invulnerable.0.tick(time.delta());
+        if invulnerable.0.is_finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dying_restores_health_with_a_moment_of_invulnerability() {
+        let mut app = App::new();
+        app.init_resource::<HealthConfig>()
+            .insert_resource(SaveSettings::default())
+            .add_observer(on_player_died);
+        let player = app.world_mut().spawn((Player, Health(0))).id();
+
+        app.world_mut().trigger(PlayerDied);
+        app.world_mut().flush();
+        let max_health = app.world().resource::<HealthConfig>().max_health;
+        assert_eq!(app.world().get::<Health>(player).unwrap().0, max_health);
+        assert!(app.world().get::<Invulnerable>(player).is_some());
+    }
+}
@@ -0,0 +1,457 @@
+use avian3d::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    components::{
+        gamestate::{ActivityState, LocationState},
+        health::Health,
+        player::Player,
+        world::WorldId,
+    },
+    plugins::{
+        cutscene::{CutsceneFlags, CutsceneSequence},
+        decoration::{PlacedFurniture, ShellLayout},
+        input::KeyBindings,
+        inventory::PlayerInventory,
+        world_streaming::{SavedWorld, StreamedEntity, WorldStreaming},
+        world_switching::{Checkpoint, ReturnCheckpoints, WorldRegistry},
+    },
+    save_migration::{CURRENT_VERSION, MigrationError, migrate},
+};
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveSettings>()
+            .register_type::<SaveSettings>()
+            .add_systems(
+                Update,
+                (
+                    quick_save_and_load.run_if(in_state(ActivityState::Playing)),
+                    // reads the world switches that happen during the transition cutscene
+                    autosave_after_world_switch,
+                ),
+            )
+            .add_observer(on_save_game)
+            .add_observer(on_load_game);
+    }
+}
+
+// bumped by adding a migration whenever SaveFile changes shape
+pub const SAVE_VERSION: u32 = CURRENT_VERSION;
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct SaveSettings {
+    directory: PathBuf,
+    // the manual slot used by the quick save and quick load keys
+    quick_slot: u8,
+}
+
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("saves"),
+            quick_slot: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveSlot {
+    Autosave,
+    Manual(u8),
+}
+
+impl SaveSlot {
+    fn path(self, settings: &SaveSettings) -> PathBuf {
+        match self {
+            SaveSlot::Autosave => settings.directory.join("autosave.ron"),
+            SaveSlot::Manual(slot) => settings.directory.join(format!("slot{slot}.ron")),
+        }
+    }
+
+    pub fn exists(self, settings: &SaveSettings) -> bool {
+        self.path(settings).exists()
+    }
+}
+
+impl fmt::Display for SaveSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveSlot::Autosave => f.write_str("autosave"),
+            SaveSlot::Manual(slot) => write!(f, "slot {slot}"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub player: PlayerSave,
+    pub inventory: Vec<Option<String>>,
+    pub location: WorldId,
+    pub checkpoints: Vec<Checkpoint>,
+    // every world the player has visited, including the current one
+    pub worlds: HashMap<WorldId, SavedWorld>,
+    pub shell_layout: Vec<PlacedFurniture>,
+    pub flags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub transform: Transform,
+    pub health: Option<u16>,
+}
+
+// only the version, read first to know which migrations the rest of the file needs
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Corrupt(ron::error::SpannedError),
+    Invalid(ron::Error),
+    Serialize(ron::Error),
+    Migration(MigrationError),
+    UnknownWorld(WorldId),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "could not access save file: {err}"),
+            SaveError::Corrupt(err) => write!(f, "save file is corrupt: {err}"),
+            SaveError::Invalid(err) => write!(f, "save file has unexpected contents: {err}"),
+            SaveError::Serialize(err) => write!(f, "could not write save file: {err}"),
+            SaveError::Migration(err) => err.fmt(f),
+            SaveError::UnknownWorld(world) => {
+                write!(f, "save file refers to unknown world {world}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl SaveFile {
+    pub fn read(path: &Path) -> Result<Self, SaveError> {
+        let text = fs::read_to_string(path).map_err(SaveError::Io)?;
+        let header: SaveHeader = ron::from_str(&text).map_err(SaveError::Corrupt)?;
+        let save: ron::Value = ron::from_str(&text).map_err(SaveError::Corrupt)?;
+        migrate(save, header.version)
+            .map_err(SaveError::Migration)?
+            .into_rust()
+            .map_err(SaveError::Invalid)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), SaveError> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(SaveError::Serialize)?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(SaveError::Io)?;
+        }
+        fs::write(path, text).map_err(SaveError::Io)
+    }
+
+    // everything the save refers to has to exist before any of it is applied
+    fn validate(&self, registry: &WorldRegistry) -> Result<(), SaveError> {
+        let worlds = std::iter::once(&self.location)
+            .chain(self.checkpoints.iter().map(|checkpoint| &checkpoint.world))
+            .chain(self.worlds.keys());
+        for world in worlds {
+            if registry.get(world).is_none() {
+                return Err(SaveError::UnknownWorld(world.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Event)]
+pub struct SaveGame(pub SaveSlot);
+
+#[derive(Event)]
+pub struct LoadGame(pub SaveSlot);
+
+#[derive(SystemParam)]
+struct GameData<'w, 's> {
+    commands: Commands<'w, 's>,
+    player: Single<
+        'w,
+        's,
+        (
+            &'static mut Transform,
+            &'static mut Position,
+            &'static mut Health,
+        ),
+        With<Player>,
+    >,
+    inventory: ResMut<'w, PlayerInventory>,
+    location: Res<'w, State<LocationState>>,
+    next_location: ResMut<'w, NextState<LocationState>>,
+    checkpoints: ResMut<'w, ReturnCheckpoints>,
+    streaming: ResMut<'w, WorldStreaming>,
+    entities: Query<'w, 's, StreamedEntity>,
+    world_entities: Query<'w, 's, Entity, With<WorldId>>,
+    layout: ResMut<'w, ShellLayout>,
+    flags: ResMut<'w, CutsceneFlags>,
+    registry: Res<'w, WorldRegistry>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl GameData<'_, '_> {
+    fn capture(&self) -> SaveFile {
+        let (transform, _, health) = &*self.player;
+        let mut flags: Vec<String> = self.flags.0.iter().cloned().collect();
+        flags.sort();
+        SaveFile {
+            version: SAVE_VERSION,
+            player: PlayerSave {
+                transform: **transform,
+                health: Some(health.0),
+            },
+            inventory: self.inventory.slots.clone(),
+            location: self.location.0.clone(),
+            checkpoints: self.checkpoints.0.clone(),
+            worlds: self.streaming.snapshot(&self.entities),
+            shell_layout: self.layout.furniture.clone(),
+            flags,
+        }
+    }
+
+    fn restore(&mut self, save: SaveFile) {
+        let (transform, position, health) = &mut *self.player;
+        **transform = save.player.transform;
+        position.0 = save.player.transform.translation;
+        // a save taken at zero health would leave the player dead without dying
+        if let Some(saved) = save.player.health {
+            health.0 = saved.max(1);
+        }
+        self.inventory.slots = save.inventory;
+        self.checkpoints.0 = save.checkpoints;
+        self.layout.furniture = save.shell_layout;
+        self.flags.0 = save.flags.into_iter().collect();
+
+        // rebuild the saved world from scratch, whichever one is spawned right now
+        for entity in &self.world_entities {
+            self.commands.entity(entity).despawn();
+        }
+        self.streaming.restore(
+            save.worlds,
+            &save.location,
+            &self.registry,
+            &self.asset_server,
+        );
+        if self.location.0 != save.location {
+            self.next_location.set(LocationState(save.location));
+        }
+    }
+}
+
+fn on_save_game(event: On<SaveGame>, game: GameData, settings: Res<SaveSettings>) {
+    let path = event.0.path(&settings);
+    match game.capture().write(&path) {
+        Ok(()) => info!("saved to {}", event.0),
+        Err(err) => error!("could not save to {}: {err}", event.0),
+    }
+}
+
+fn on_load_game(
+    event: On<LoadGame>,
+    mut game: GameData,
+    settings: Res<SaveSettings>,
+    current_activity: Res<State<ActivityState>>,
+    cutscene: Res<CutsceneSequence>,
+) {
+    // a running cutscene or dialogue would keep acting on the world that's being replaced
+    if *current_activity != ActivityState::Playing || cutscene.is_busy() {
+        return;
+    }
+    let save = SaveFile::read(&event.0.path(&settings))
+        .and_then(|save| save.validate(&game.registry).map(|()| save));
+    match save {
+        Ok(save) => {
+            game.restore(save);
+            info!("loaded {}", event.0);
+        }
+        Err(err) => error!("could not load {}: {err}", event.0),
+    }
+}
+
+fn quick_save_and_load(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
+    settings: Res<SaveSettings>,
+) {
+    let slot = SaveSlot::Manual(settings.quick_slot);
+    if keys.just_pressed(keybinds.quick_save) {
+        commands.trigger(SaveGame(slot));
+    } else if keys.just_pressed(keybinds.quick_load) {
+        commands.trigger(LoadGame(slot));
+    }
+}
+
+// waits until the switch has fully played out so the player is already in the new world
+fn autosave_after_world_switch(
+    mut commands: Commands,
+    mut transitions: MessageReader<StateTransitionEvent<LocationState>>,
+    mut pending: Local<bool>,
+    current_location: Res<State<LocationState>>,
+    current_activity: Res<State<ActivityState>>,
+    streaming: Res<WorldStreaming>,
+    cutscene: Res<CutsceneSequence>,
+) {
+    for transition in transitions.read() {
+        if transition.exited.is_some() && transition.exited != transition.entered {
+            *pending = true;
+        }
+    }
+    if *pending
+        && *current_activity == ActivityState::Playing
+        && streaming.is_ready(&current_location.0)
+        && !cutscene.is_busy()
+    {
+        *pending = false;
+        commands.trigger(SaveGame(SaveSlot::Autosave));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::world_switching::WorldDefinition;
+    use std::collections::HashSet;
+
+    const GOLDEN_SAVES: &str = "tests/saves";
+
+    fn registry() -> WorldRegistry {
+        let mut registry = WorldRegistry::default();
+        for world in [WorldId::OUTSIDE, WorldId::HUT, WorldId::SHELL] {
+            registry.register(
+                world.clone(),
+                WorldDefinition {
+                    name: world.to_string(),
+                    level: format!("levels/{world}.level.ron"),
+                    combat: false,
+                },
+            );
+        }
+        registry
+    }
+
+    fn golden(version: u32) -> PathBuf {
+        Path::new(GOLDEN_SAVES).join(format!("v{version}.ron"))
+    }
+
+    #[test]
+    fn every_version_has_a_golden_save() {
+        for version in 1..=SAVE_VERSION {
+            assert!(
+                golden(version).exists(),
+                "no golden save for version {version}"
+            );
+        }
+    }
+
+    #[test]
+    fn golden_saves_load_as_the_current_version() {
+        for entry in fs::read_dir(GOLDEN_SAVES).unwrap() {
+            let path = entry.unwrap().path();
+            let save = SaveFile::read(&path)
+                .unwrap_or_else(|err| panic!("{} doesn't load: {err}", path.display()));
+            assert_eq!(save.version, SAVE_VERSION, "{}", path.display());
+            save.validate(&registry()).unwrap();
+        }
+    }
+
+    #[test]
+    fn version_1_save_keeps_its_contents() {
+        let save = SaveFile::read(&golden(1)).unwrap();
+        assert_eq!(save.player.transform.translation, Vec3::new(0.0, 0.5, 2.0));
+        assert_eq!(save.player.health, Some(7));
+        assert_eq!(save.inventory.len(), 8);
+        assert_eq!(save.inventory[0].as_deref(), Some("Stool"));
+        assert_eq!(save.inventory[2].as_deref(), Some("Rug"));
+        assert_eq!(save.location, WorldId::SHELL);
+        assert_eq!(save.checkpoints.len(), 1);
+        assert_eq!(save.checkpoints[0].world, WorldId::OUTSIDE);
+        assert_eq!(save.checkpoints[0].position, Vec3::new(3.0, 0.5, -4.0));
+        assert_eq!(save.flags, vec!["met_hermit".to_owned()]);
+        assert_eq!(save.shell_layout.len(), 1);
+        assert_eq!(save.shell_layout[0].item, "Lamp");
+        assert_eq!(save.shell_layout[0].cell, IVec2::new(1, -2));
+        assert_eq!(save.shell_layout[0].rotation, 1);
+
+        let outside = &save.worlds[&WorldId::OUTSIDE];
+        let cube = &outside.entities["Dynamic Cube"];
+        assert_eq!(cube.transform.translation, Vec3::new(1.5, 0.25, -3.0));
+        assert_eq!(
+            cube.velocity,
+            Some((Vec3::new(2.0, 0.0, -1.0), Vec3::new(0.0, 3.0, 0.0)))
+        );
+        assert_eq!(outside.entities["Enemy"].health, Some(1));
+        assert!(outside.removed.contains("Chaser"));
+        assert_eq!(outside.bullets.len(), 1);
+        assert_eq!(outside.bullets[0].bullet.direction, Dir3::X);
+        assert_eq!(outside.bullets[0].bullet.current_speed, 12.0);
+        assert!(save.worlds[&WorldId::SHELL].entities.is_empty());
+        // version 1 didn't keep pickups
+        assert!(save.worlds.values().all(|world| world.pickups.is_empty()));
+    }
+
+    #[test]
+    fn version_2_save_keeps_its_pickups() {
+        let save = SaveFile::read(&golden(2)).unwrap();
+        let pickups = &save.worlds[&WorldId::OUTSIDE].pickups;
+        assert_eq!(pickups.len(), 1);
+        assert_eq!(pickups[0].item, "Stool");
+        assert_eq!(pickups[0].transform.translation, Vec3::new(-1.0, 0.1, 3.5));
+        assert!(save.worlds[&WorldId::SHELL].pickups.is_empty());
+    }
+
+    #[test]
+    fn written_saves_read_back() {
+        let save = SaveFile::read(&golden(SAVE_VERSION)).unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("save-roundtrip-{}", std::process::id()))
+            .join("slot1.ron");
+        save.write(&path).unwrap();
+        let read = SaveFile::read(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(read.version, SAVE_VERSION);
+        assert_eq!(read.player.transform, save.player.transform);
+        assert_eq!(read.inventory, save.inventory);
+        assert_eq!(read.location, save.location);
+        assert_eq!(read.flags, save.flags);
+        assert_eq!(
+            read.worlds.keys().collect::<HashSet<_>>(),
+            save.worlds.keys().collect::<HashSet<_>>()
+        );
+        let (read_outside, outside) = (
+            &read.worlds[&WorldId::OUTSIDE],
+            &save.worlds[&WorldId::OUTSIDE],
+        );
+        assert_eq!(read_outside.removed, outside.removed);
+        assert_eq!(
+            read_outside.entities["Dynamic Cube"].velocity,
+            outside.entities["Dynamic Cube"].velocity
+        );
+        assert_eq!(
+            read_outside.pickups[0].transform,
+            outside.pickups[0].transform
+        );
+    }
+}
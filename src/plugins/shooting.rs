@@ -1,10 +1,16 @@
+use crate::components::enemy::Enemy;
 use crate::components::gamelayer::GameLayer;
 use crate::components::gamestate::{ActivityState, LocationState};
 use crate::components::health::Health;
-use crate::components::player::Player;
-use crate::{components::enemy::Enemy, plugins::level::OutsideWorld};
+use crate::components::player::{AimDirection, Player, PlayerSet};
+use crate::components::world::WorldId;
+use crate::plugins::camera::AddTrauma;
+use crate::plugins::enemy::EnemyDefeated;
+use crate::plugins::world_switching::WorldRegistry;
 use avian3d::prelude::*;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 pub struct ShootingPlugin;
 
@@ -15,19 +21,29 @@ impl Plugin for ShootingPlugin {
                 Update,
                 (handle_shooting, move_bullets, handle_bullet_collisions)
                     .chain()
-                    .run_if(in_state(LocationState::Outside))
+                    .after(PlayerSet::Movement)
+                    .run_if(combat_allowed)
                     .run_if(in_state(ActivityState::Playing)),
             )
             .register_type::<GunConfig>();
     }
 }
 
+// the gun only works in worlds registered with combat enabled
+fn combat_allowed(location: Res<State<LocationState>>, registry: Res<WorldRegistry>) -> bool {
+    registry.get(&location.0).is_some_and(|world| world.combat)
+}
+
 #[derive(Resource, Reflect)]
 #[reflect(Resource)]
 pub struct GunConfig {
     starting_speed: f32,
     acceleration: f32,
     push_force: f32,
+    // camera shake added on each event
+    fire_trauma: f32,
+    impact_trauma: f32,
+    kill_trauma: f32,
 }
 
 impl Default for GunConfig {
@@ -36,65 +52,68 @@ impl Default for GunConfig {
             starting_speed: 10.0,
             acceleration: 6.0,
             push_force: 2.0,
+            fire_trauma: 0.15,
+            impact_trauma: 0.2,
+            kill_trauma: 0.5,
         }
     }
 }
 
-#[derive(Component)]
-struct Bullet {
-    direction: Dir3,
-    current_speed: f32,
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+pub struct Bullet {
+    pub direction: Dir3,
+    pub current_speed: f32,
+}
+
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    transform: Transform,
+    bullet: Bullet,
+    world: WorldId,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Sphere::new(0.1))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.0, 0.0),
+            emissive: LinearRgba::new(10000., 0., 0., 0.),
+            ..default()
+        })),
+        transform,
+        Collider::sphere(0.1),
+        bullet,
+        CollisionLayers::new(GameLayer::PlayerBullet, GameLayer::Default),
+        world,
+    ));
 }
 
 fn handle_shooting(
     mut commands: Commands,
     mouse: Res<ButtonInput<MouseButton>>,
-    windows: Query<&Window>,
-    camera: Query<(&Camera, &GlobalTransform)>,
-    player_query: Query<&Transform, With<Player>>,
+    player_query: Query<(&Transform, &AimDirection), With<Player>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     gun_config: Res<GunConfig>,
+    current_location: Res<State<LocationState>>,
 ) {
     if mouse.just_pressed(MouseButton::Left) {
-        if let Some(cursor_pos) = windows.single().unwrap().cursor_position() {
-            let (camera, camera_transform) = camera.single().unwrap();
-
-            if let Ok(ray) = camera.viewport_to_world(camera_transform, cursor_pos) {
-                let player_transform = player_query.single().unwrap();
-                // Find intersection with plane at player's Z height
-                let player_height = player_transform.translation.y;
-                let t = (player_height - ray.origin.y) / ray.direction.y;
-
-                if t >= 0.0 {
-                    let point = ray.get_point(t);
-                    let direction = Vec3::new(
-                        point.x - player_transform.translation.x,
-                        0.0,
-                        point.z - player_transform.translation.z,
-                    )
-                    .normalize();
-
-                    // Spawn bullet with velocity in that direction
-                    commands.spawn((
-                        Mesh3d(meshes.add(Sphere::new(0.1))),
-                        MeshMaterial3d(materials.add(StandardMaterial {
-                            base_color: Color::srgb(1.0, 0.0, 0.0),
-                            emissive: LinearRgba::new(10000., 0., 0., 0.),
-                            ..default()
-                        })),
-                        Transform::from_translation(player_transform.translation),
-                        Collider::sphere(0.1),
-                        Bullet {
-                            direction: Dir3::new_unchecked(direction),
-                            current_speed: gun_config.starting_speed,
-                        },
-                        CollisionLayers::new(GameLayer::PlayerBullet, GameLayer::Default),
-                        OutsideWorld,
-                    ));
-                }
-            }
-        }
+        // the aim already follows the selected facing mode (cursor, movement or lock-on)
+        let (player_transform, aim_direction) = player_query.single().unwrap();
+
+        // Spawn bullet with velocity in that direction
+        spawn_bullet(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            Transform::from_translation(player_transform.translation),
+            Bullet {
+                direction: aim_direction.0,
+                current_speed: gun_config.starting_speed,
+            },
+            current_location.0.clone(),
+        );
+        commands.trigger(AddTrauma(gun_config.fire_trauma));
     }
 }
 
@@ -121,6 +140,8 @@ fn handle_bullet_collisions(
     gun_config: Res<GunConfig>,
 ) {
     let (player_entity, player_transform) = player_query.single().unwrap();
+    // despawning is deferred, so a defeated enemy can still be hit by another bullet this frame
+    let mut defeated = HashSet::new();
 
     for (bullet_entity, bullet_transform, bullet, bullet_collider) in &mut bullets {
         {
@@ -150,11 +171,92 @@ fn handle_bullet_collisions(
             if let Ok(mut health) = query_enemy.get_mut(hit.entity) {
                 if let Some(new_health) = health.0.checked_sub(1) {
                     health.0 = new_health;
-                } else {
+                } else if defeated.insert(hit.entity) {
+                    commands.trigger(EnemyDefeated(hit.entity));
                     commands.entity(hit.entity).despawn();
+                    commands.trigger(AddTrauma(gun_config.kill_trauma));
                 }
             }
+            commands.trigger(AddTrauma(gun_config.impact_trauma));
             commands.entity(bullet_entity).despawn();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    #[derive(Resource, Default)]
+    struct Defeats(Vec<Entity>);
+
+    fn shooting_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            AssetPlugin::default(),
+            TransformPlugin,
+            bevy::scene::ScenePlugin,
+            PhysicsPlugins::default(),
+        ))
+        .init_asset::<Mesh>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+            1.0 / 60.0,
+        )))
+        .init_resource::<GunConfig>()
+        .init_resource::<Defeats>()
+        .add_systems(Update, handle_bullet_collisions)
+        .add_observer(|event: On<EnemyDefeated>, mut defeats: ResMut<Defeats>| {
+            defeats.0.push(event.0);
+        });
+        // physics sets up part of itself in Plugin::finish, which only App::run calls otherwise
+        app.finish();
+        app.cleanup();
+        app.world_mut()
+            .spawn((Player, Transform::from_xyz(-5.0, 0.5, 0.0)));
+        app
+    }
+
+    fn spawn_bullet_at(app: &mut App, position: Vec3) {
+        app.world_mut().spawn((
+            Transform::from_translation(position),
+            Collider::sphere(0.1),
+            Bullet {
+                direction: Dir3::X,
+                current_speed: 10.0,
+            },
+        ));
+    }
+
+    #[test]
+    fn an_enemy_hit_by_two_bullets_at_once_is_defeated_once() {
+        let mut app = shooting_app();
+        // one hit away from being defeated
+        let enemy = app
+            .world_mut()
+            .spawn((
+                Enemy,
+                Health(0),
+                Transform::from_xyz(0.0, 0.5, 0.0),
+                Collider::cuboid(1.0, 1.0, 1.0),
+                RigidBody::Static,
+            ))
+            .id();
+        // let physics pick up the enemy's collider for spatial queries
+        for _ in 0..5 {
+            app.update();
+        }
+
+        spawn_bullet_at(&mut app, Vec3::new(-0.55, 0.5, 0.1));
+        spawn_bullet_at(&mut app, Vec3::new(-0.55, 0.5, -0.1));
+        app.update();
+
+        assert_eq!(app.world().resource::<Defeats>().0, vec![enemy]);
+        assert!(app.world().get_entity(enemy).is_err());
+        let mut bullets = app.world_mut().query::<&Bullet>();
+        assert_eq!(bullets.iter(app.world()).count(), 0);
+    }
+}
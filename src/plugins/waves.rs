@@ -0,0 +1,448 @@
+use avian3d::prelude::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::f32::consts::TAU;
+
+use crate::{
+    components::{
+        gamelayer::GameLayer,
+        gamestate::{ActivityState, LocationState},
+        world::WorldId,
+    },
+    plugins::{enemy::spawn_enemy, world_streaming::WorldSpawned},
+};
+
+pub struct WavePlugin;
+
+impl Plugin for WavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WaveSettings>()
+            .init_resource::<WaveDirector>()
+            .register_type::<WaveSettings>()
+            .add_systems(
+                Update,
+                (spawn_wave_enemies, direct_waves)
+                    .chain()
+                    .run_if(in_state(ActivityState::Playing))
+                    .run_if(|director: Res<WaveDirector>| director.is_active()),
+            )
+            .add_systems(Update, abandon_arena)
+            .add_observer(on_start_arena)
+            .add_observer(on_wave_started)
+            .add_observer(on_wave_cleared)
+            .add_observer(on_world_spawned);
+    }
+}
+
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+pub struct WaveSettings {
+    // seconds between a wave being cleared and the next one starting
+    rest: f32,
+    // added to the difficulty every wave, it scales enemy counts, max alive and spawn rate
+    escalation: f32,
+    // free space an enemy needs around the point it's spawned at
+    spawn_clearance: f32,
+    // points tried per spawn before waiting for the next one
+    spawn_attempts: u32,
+}
+
+impl Default for WaveSettings {
+    fn default() -> Self {
+        Self {
+            rest: 3.0,
+            escalation: 0.25,
+            spawn_clearance: 0.4,
+            spawn_attempts: 8,
+        }
+    }
+}
+
+// a spawner as placed in a level file
+#[derive(Clone, Deserialize)]
+pub struct SpawnerDefinition {
+    pub position: Vec3,
+    // enemies appear anywhere within this distance of the spawner
+    pub radius: f32,
+    pub max_alive: u32,
+    // seconds between two enemies
+    pub interval: f32,
+    // the last wave repeats once they run out
+    pub waves: Vec<SpawnerWave>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct SpawnerWave {
+    pub enemies: Vec<WaveEnemy>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WaveEnemy {
+    // file name in assets/enemies, without the extension
+    pub archetype: String,
+    pub count: u32,
+}
+
+#[derive(Component)]
+pub struct EnemySpawner {
+    definition: SpawnerDefinition,
+    // archetypes still to spawn this wave, popped from the back
+    queue: Vec<String>,
+    max_alive: u32,
+    cooldown: Timer,
+    // how many points have been tried so far, spreads the spawns over the radius
+    attempts: u32,
+}
+
+// the spawner an enemy came from
+#[derive(Component)]
+struct SpawnedBy(Entity);
+
+pub fn spawn_enemy_spawner(
+    commands: &mut Commands,
+    definition: &SpawnerDefinition,
+    world: WorldId,
+) -> Entity {
+    commands
+        .spawn((
+            EnemySpawner {
+                definition: definition.clone(),
+                queue: Vec::new(),
+                max_alive: 0,
+                cooldown: Timer::from_seconds(definition.interval, TimerMode::Repeating),
+                attempts: 0,
+            },
+            Transform::from_translation(definition.position),
+            Name::new("Enemy Spawner"),
+            world,
+        ))
+        .id()
+}
+
+#[derive(Default)]
+enum WavePhase {
+    #[default]
+    Idle,
+    Fighting,
+    Resting(Timer),
+}
+
+// runs the arena, every spawner in the current world takes part in the same wave. leaving the
+// arena's world abandons it, it starts over from the first wave the next time it's started
+#[derive(Resource, Default)]
+pub struct WaveDirector {
+    phase: WavePhase,
+    wave: u32,
+    // the world the arena was started in, None while idle
+    world: Option<WorldId>,
+}
+
+impl WaveDirector {
+    pub fn is_active(&self) -> bool {
+        !matches!(self.phase, WavePhase::Idle)
+    }
+
+    pub fn difficulty(&self, settings: &WaveSettings) -> f32 {
+        1.0 + settings.escalation * self.wave.saturating_sub(1) as f32
+    }
+}
+
+#[derive(Event)]
+pub struct StartArena;
+
+#[derive(Event)]
+pub struct WaveStarted(pub u32);
+
+#[derive(Event)]
+pub struct WaveCleared(pub u32);
+
+fn on_start_arena(
+    _event: On<StartArena>,
+    mut commands: Commands,
+    mut director: ResMut<WaveDirector>,
+    location: Res<State<LocationState>>,
+    spawners: Query<(), With<EnemySpawner>>,
+) {
+    if director.is_active() || spawners.is_empty() {
+        return;
+    }
+    director.phase = WavePhase::Fighting;
+    director.wave = 1;
+    director.world = Some(location.0.clone());
+    commands.trigger(WaveStarted(1));
+}
+
+fn on_wave_started(
+    event: On<WaveStarted>,
+    director: Res<WaveDirector>,
+    settings: Res<WaveSettings>,
+    mut spawners: Query<&mut EnemySpawner>,
+) {
+    info!("wave {} started", event.0);
+    let difficulty = director.difficulty(&settings);
+    for mut spawner in &mut spawners {
+        let Some(wave) = spawner
+            .definition
+            .waves
+            .get(event.0.saturating_sub(1) as usize)
+            .or(spawner.definition.waves.last())
+            .cloned()
+        else {
+            continue;
+        };
+        spawner.queue = wave
+            .enemies
+            .iter()
+            .flat_map(|enemy| {
+                let count = (enemy.count as f32 * difficulty).ceil() as usize;
+                std::iter::repeat_n(enemy.archetype.clone(), count)
+            })
+            .rev()
+            .collect();
+        spawner.max_alive = (spawner.definition.max_alive as f32 * difficulty).ceil() as u32;
+        let interval = spawner.definition.interval / difficulty;
+        spawner.cooldown = Timer::from_seconds(interval, TimerMode::Repeating);
+    }
+}
+
+fn on_wave_cleared(event: On<WaveCleared>) {
+    info!("wave {} cleared", event.0);
+}
+
+fn abandon_arena(
+    mut transitions: MessageReader<StateTransitionEvent<LocationState>>,
+    mut director: ResMut<WaveDirector>,
+) {
+    for transition in transitions.read() {
+        let left = transition.exited != transition.entered
+            && transition
+                .exited
+                .as_ref()
+                .is_some_and(|LocationState(world)| director.world.as_ref() == Some(world));
+        if left {
+            info!("arena abandoned in wave {}", director.wave);
+            *director = WaveDirector::default();
+        }
+    }
+}
+
+// loading a save into the arena's world respawns its spawners empty, they pick the wave back up
+fn on_world_spawned(event: On<WorldSpawned>, mut commands: Commands, director: Res<WaveDirector>) {
+    if matches!(director.phase, WavePhase::Fighting) && director.world.as_ref() == Some(&event.0) {
+        commands.trigger(WaveStarted(director.wave));
+    }
+}
+
+fn spawn_wave_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<WaveSettings>,
+    spatial_query: SpatialQuery,
+    mut spawners: Query<(Entity, &mut EnemySpawner, &Transform, &WorldId)>,
+    spawned: Query<&SpawnedBy>,
+) {
+    let probe = Collider::sphere(settings.spawn_clearance);
+    let filter = SpatialQueryFilter::from_mask(GameLayer::Default);
+    for (entity, mut spawner, transform, world) in &mut spawners {
+        spawner.cooldown.tick(time.delta());
+        if !spawner.cooldown.just_finished() || spawner.queue.is_empty() {
+            continue;
+        }
+        let alive = spawned.iter().filter(|spawned| spawned.0 == entity).count();
+        if alive as u32 >= spawner.max_alive {
+            continue;
+        }
+
+        for _ in 0..settings.spawn_attempts {
+            // golden angle steps with a square root radius cover the circle evenly
+            spawner.attempts += 1;
+            let n = spawner.attempts as f32;
+            let angle = n * TAU * 0.381_966;
+            let distance = spawner.definition.radius * (n * 0.618_034).fract().sqrt();
+            let position =
+                transform.translation + Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+            if !spatial_query
+                .shape_intersections(&probe, position, Quat::IDENTITY, &filter)
+                .is_empty()
+            {
+                continue;
+            }
+            let archetype = spawner.queue.pop().unwrap();
+            spawn_enemy(
+                &mut commands,
+                &archetype,
+                Transform::from_translation(position),
+                world.clone(),
+            )
+            .insert(SpawnedBy(entity));
+            break;
+        }
+    }
+}
+
+fn direct_waves(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<WaveSettings>,
+    mut director: ResMut<WaveDirector>,
+    spawners: Query<&EnemySpawner>,
+    spawned: Query<(), With<SpawnedBy>>,
+) {
+    let wave = director.wave;
+    match &mut director.phase {
+        WavePhase::Idle => {}
+        WavePhase::Fighting => {
+            // no spawners means the world is still streaming back in, e.g. after loading a save
+            if !spawners.is_empty()
+                && spawned.is_empty()
+                && spawners.iter().all(|spawner| spawner.queue.is_empty())
+            {
+                director.phase =
+                    WavePhase::Resting(Timer::from_seconds(settings.rest, TimerMode::Once));
+                commands.trigger(WaveCleared(wave));
+            }
+        }
+        WavePhase::Resting(timer) => {
+            if timer.tick(time.delta()).is_finished() {
+                director.phase = WavePhase::Fighting;
+                director.wave += 1;
+                commands.trigger(WaveStarted(director.wave));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    fn wave_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            // the virtual clock never advances more than 250ms per update
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                250,
+            )))
+            .init_state::<ActivityState>()
+            .init_state::<LocationState>()
+            .init_resource::<WaveSettings>()
+            .init_resource::<WaveDirector>()
+            // spawn_wave_enemies needs physics, the tests spawn the enemies themselves
+            .add_systems(Update, (direct_waves, abandon_arena))
+            .add_observer(on_start_arena)
+            .add_observer(on_wave_started)
+            .add_observer(on_world_spawned);
+        app
+    }
+
+    fn add_spawner(app: &mut App) -> Entity {
+        let definition = SpawnerDefinition {
+            position: Vec3::ZERO,
+            radius: 2.0,
+            max_alive: 3,
+            interval: 2.0,
+            waves: vec![SpawnerWave {
+                enemies: vec![WaveEnemy {
+                    archetype: "cube".into(),
+                    count: 2,
+                }],
+            }],
+        };
+        let mut commands = app.world_mut().commands();
+        let spawner = spawn_enemy_spawner(&mut commands, &definition, WorldId::OUTSIDE);
+        app.world_mut().flush();
+        spawner
+    }
+
+    fn spawner(app: &App, entity: Entity) -> &EnemySpawner {
+        app.world().get::<EnemySpawner>(entity).unwrap()
+    }
+
+    // what spawn_wave_enemies would do, every queued enemy appears and is killed right away
+    fn spawn_and_kill_wave(app: &mut App, entity: Entity) {
+        let queued = std::mem::take(
+            &mut app
+                .world_mut()
+                .get_mut::<EnemySpawner>(entity)
+                .unwrap()
+                .queue,
+        );
+        let enemies: Vec<Entity> = queued
+            .iter()
+            .map(|_| app.world_mut().spawn(SpawnedBy(entity)).id())
+            .collect();
+        app.update();
+        assert!(matches!(
+            app.world().resource::<WaveDirector>().phase,
+            WavePhase::Fighting
+        ));
+        for enemy in enemies {
+            app.world_mut().despawn(enemy);
+        }
+    }
+
+    #[test]
+    fn cleared_waves_rest_and_come_back_harder() {
+        let mut app = wave_app();
+        app.update();
+        let entity = add_spawner(&mut app);
+        app.world_mut().trigger(StartArena);
+        app.world_mut().flush();
+
+        let first = spawner(&app, entity);
+        assert_eq!(first.queue.len(), 2);
+        assert_eq!(first.max_alive, 3);
+        assert_eq!(first.cooldown.duration(), Duration::from_secs(2));
+
+        spawn_and_kill_wave(&mut app, entity);
+        app.update();
+        let director = app.world().resource::<WaveDirector>();
+        assert!(matches!(director.phase, WavePhase::Resting(_)));
+        assert_eq!(director.wave, 1);
+
+        // WaveSettings::rest is 3 seconds
+        for _ in 0..13 {
+            app.update();
+        }
+        let director = app.world().resource::<WaveDirector>();
+        assert!(matches!(director.phase, WavePhase::Fighting));
+        assert_eq!(director.wave, 2);
+        let settings = app.world().resource::<WaveSettings>();
+        assert_eq!(director.difficulty(settings), 1.25);
+
+        // the last wave repeats, scaled by the difficulty
+        let second = spawner(&app, entity);
+        assert_eq!(second.queue.len(), 3);
+        assert_eq!(second.max_alive, 4);
+        assert_eq!(
+            second.cooldown.duration(),
+            Duration::from_secs_f32(2.0 / 1.25)
+        );
+    }
+
+    #[test]
+    fn leaving_the_world_abandons_the_arena() {
+        let mut app = wave_app();
+        app.update();
+        let entity = add_spawner(&mut app);
+        app.world_mut().trigger(StartArena);
+        app.world_mut().flush();
+
+        // a save loaded into the same world keeps the arena going
+        app.world_mut().trigger(WorldSpawned(WorldId::OUTSIDE));
+        app.world_mut().flush();
+        app.update();
+        assert!(app.world().resource::<WaveDirector>().is_active());
+        assert_eq!(spawner(&app, entity).queue.len(), 2);
+
+        app.world_mut()
+            .resource_mut::<NextState<LocationState>>()
+            .set(LocationState(WorldId::HUT));
+        app.update();
+        let director = app.world().resource::<WaveDirector>();
+        assert!(!director.is_active());
+        assert_eq!(director.world, None);
+    }
+}
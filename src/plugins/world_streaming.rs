@@ -0,0 +1,437 @@
+use avian3d::prelude::*;
+use bevy::{asset::LoadState, ecs::query::QueryData, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    components::{
+        gamestate::LocationState,
+        health::Health,
+        world::{LevelKey, WorldId},
+    },
+    plugins::{
+        level::{Level, spawn_level},
+        loot::{Pickup, PickupAssets, spawn_pickup},
+        shooting::{Bullet, spawn_bullet},
+        world_switching::WorldRegistry,
+    },
+};
+
+pub struct WorldStreamingPlugin;
+
+impl Plugin for WorldStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldStreaming>();
+        app.add_systems(Update, (switch_streamed_world, spawn_loaded_world).chain());
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedEntity {
+    pub transform: Transform,
+    pub health: Option<u16>,
+    // linear and angular velocity of dynamic bodies, so they keep moving where they left off
+    pub velocity: Option<(Vec3, Vec3)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedBullet {
+    pub transform: Transform,
+    pub bullet: Bullet,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedPickup {
+    pub transform: Transform,
+    pub item: String,
+}
+
+// state of a world while it's unloaded, keyed by LevelKey
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SavedWorld {
+    pub entities: HashMap<String, SavedEntity>,
+    // named props that were despawned, e.g. killed enemies
+    pub removed: HashSet<String>,
+    // bullets in flight when the world was left
+    pub bullets: Vec<SavedBullet>,
+    // loot lying on the ground
+    pub pickups: Vec<SavedPickup>,
+}
+
+struct StreamedWorld {
+    id: WorldId,
+    level: Handle<Level>,
+    spawned: bool,
+    keys: HashSet<String>,
+    spawn_points: HashMap<String, Vec3>,
+}
+
+// only the current world is spawned, the others are kept as saved state
+#[derive(Resource, Default)]
+pub struct WorldStreaming {
+    current: Option<StreamedWorld>,
+    saved: HashMap<WorldId, SavedWorld>,
+}
+
+impl WorldStreaming {
+    // true once `world` has finished loading and is spawned
+    pub fn is_ready(&self, world: &WorldId) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|current| current.id == *world && current.spawned)
+    }
+
+    // a named spawn point of the current world, once it's spawned
+    pub fn spawn_point(&self, name: &str) -> Option<Vec3> {
+        let current = self.current.as_ref()?;
+        current.spawn_points.get(name).copied()
+    }
+
+    // the state of every world, with the current one captured as it is right now
+    pub fn snapshot(&self, entities: &Query<StreamedEntity>) -> HashMap<WorldId, SavedWorld> {
+        let mut worlds = self.saved.clone();
+        if let Some(current) = self.current.as_ref().filter(|current| current.spawned) {
+            capture_world(
+                worlds.entry(current.id.clone()).or_default(),
+                current,
+                entities,
+            );
+        }
+        worlds
+    }
+
+    // forgets what's streamed in and starts loading `world` with `worlds` as the saved state,
+    // the caller despawns the entities of the current world
+    pub fn restore(
+        &mut self,
+        worlds: HashMap<WorldId, SavedWorld>,
+        world: &WorldId,
+        registry: &WorldRegistry,
+        asset_server: &AssetServer,
+    ) {
+        self.saved = worlds;
+        self.current = None;
+        self.load(world, registry, asset_server);
+    }
+
+    fn load(&mut self, world: &WorldId, registry: &WorldRegistry, asset_server: &AssetServer) {
+        let Some(definition) = registry.get(world) else {
+            warn!("entered unregistered world {world}");
+            return;
+        };
+        info!("loading {}", definition.name);
+        self.current = Some(StreamedWorld {
+            id: world.clone(),
+            level: asset_server.load(&definition.level),
+            spawned: false,
+            keys: HashSet::new(),
+            spawn_points: HashMap::new(),
+        });
+    }
+}
+
+// a world finished streaming in, for anything spawned on top of its level
+#[derive(Event)]
+pub struct WorldSpawned(pub WorldId);
+
+#[derive(QueryData)]
+pub struct StreamedEntity {
+    entity: Entity,
+    world: &'static WorldId,
+    transform: &'static Transform,
+    key: Option<&'static LevelKey>,
+    health: Option<&'static Health>,
+    velocity: Option<(&'static LinearVelocity, &'static AngularVelocity)>,
+    bullet: Option<&'static Bullet>,
+    pickup: Option<&'static Pickup>,
+}
+
+fn switch_streamed_world(
+    mut commands: Commands,
+    mut transitions: MessageReader<StateTransitionEvent<LocationState>>,
+    mut streaming: ResMut<WorldStreaming>,
+    registry: Res<WorldRegistry>,
+    asset_server: Res<AssetServer>,
+    entities: Query<StreamedEntity>,
+) {
+    for transition in transitions.read() {
+        let Some(LocationState(entered)) = &transition.entered else {
+            continue;
+        };
+        if streaming
+            .current
+            .as_ref()
+            .is_some_and(|current| current.id == *entered)
+        {
+            continue;
+        }
+
+        if let Some(previous) = streaming.current.take() {
+            if previous.spawned {
+                let saved = streaming.saved.entry(previous.id.clone()).or_default();
+                capture_world(saved, &previous, &entities);
+            }
+            for entity in &entities {
+                if *entity.world == previous.id {
+                    commands.entity(entity.entity).despawn();
+                }
+            }
+        }
+        streaming.load(entered, &registry, &asset_server);
+    }
+}
+
+// overwrites `saved` with the live state of `world`
+fn capture_world(saved: &mut SavedWorld, world: &StreamedWorld, entities: &Query<StreamedEntity>) {
+    saved.entities.clear();
+    saved.bullets.clear();
+    saved.pickups.clear();
+    let mut alive = HashSet::new();
+    for entity in entities {
+        if *entity.world != world.id {
+            continue;
+        }
+        if let Some(LevelKey(key)) = entity.key {
+            alive.insert(key.clone());
+            saved.entities.insert(
+                key.clone(),
+                SavedEntity {
+                    transform: *entity.transform,
+                    health: entity.health.map(|health| health.0),
+                    velocity: entity
+                        .velocity
+                        .map(|(linear, angular)| (linear.0, angular.0)),
+                },
+            );
+        }
+        if let Some(bullet) = entity.bullet {
+            saved.bullets.push(SavedBullet {
+                transform: *entity.transform,
+                bullet: *bullet,
+            });
+        }
+        if let Some(pickup) = entity.pickup {
+            saved.pickups.push(SavedPickup {
+                transform: *entity.transform,
+                item: pickup.item.clone(),
+            });
+        }
+    }
+    saved.removed.extend(world.keys.difference(&alive).cloned());
+}
+
+fn spawn_loaded_world(
+    mut commands: Commands,
+    mut streaming: ResMut<WorldStreaming>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    levels: Res<Assets<Level>>,
+    asset_server: Res<AssetServer>,
+    pickup_assets: Res<PickupAssets>,
+) {
+    let WorldStreaming { current, saved } = &mut *streaming;
+    let Some(current) = current.as_mut().filter(|current| !current.spawned) else {
+        return;
+    };
+    let Some(level) = levels.get(&current.level) else {
+        if let LoadState::Failed(err) = asset_server.load_state(&current.level) {
+            // don't leave transitions waiting on a world that will never arrive
+            error!("could not load world {}: {err}", current.id);
+            current.spawned = true;
+        }
+        return;
+    };
+    let saved = saved.get_mut(&current.id);
+    current.keys = spawn_level(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &current.id,
+        level,
+        saved.as_deref(),
+    );
+    if let Some(saved) = saved {
+        for bullet in saved.bullets.drain(..) {
+            spawn_bullet(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                bullet.transform,
+                bullet.bullet,
+                current.id.clone(),
+            );
+        }
+        // they already lay there long enough to be picked up
+        for pickup in saved.pickups.drain(..) {
+            spawn_pickup(
+                &mut commands,
+                &pickup_assets,
+                &mut materials,
+                pickup.transform,
+                &pickup.item,
+                0.0,
+                current.id.clone(),
+            );
+        }
+    }
+    current.spawn_points = level.spawn_points.clone();
+    current.spawned = true;
+    commands.trigger(WorldSpawned(current.id.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{plugins::world_switching::WorldDefinition, ron_asset::RonAssetLoader};
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin};
+
+    fn streaming_app() -> App {
+        let mut registry = WorldRegistry::default();
+        for (id, level) in [
+            (WorldId::OUTSIDE, "levels/outside.level.ron"),
+            (WorldId::HUT, "levels/hut.level.ron"),
+        ] {
+            registry.register(
+                id.clone(),
+                WorldDefinition {
+                    name: id.to_string(),
+                    level: level.into(),
+                    combat: false,
+                },
+            );
+        }
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            AssetPlugin::default(),
+            WorldStreamingPlugin,
+        ))
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .init_asset::<Level>()
+        .init_asset_loader::<RonAssetLoader<Level>>()
+        .init_resource::<PickupAssets>()
+        .insert_resource(registry)
+        .init_state::<LocationState>();
+        app
+    }
+
+    // updates until `world` is streamed in and its entities exist
+    fn wait_for_world(app: &mut App, world: &WorldId) {
+        for _ in 0..1000 {
+            app.update();
+            if app.world().resource::<WorldStreaming>().is_ready(world) {
+                app.update();
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        panic!("{world} never streamed in");
+    }
+
+    fn switch_to(app: &mut App, world: WorldId) {
+        app.world_mut()
+            .resource_mut::<NextState<LocationState>>()
+            .set(LocationState(world.clone()));
+        wait_for_world(app, &world);
+    }
+
+    fn keyed(app: &mut App, key: &str) -> Option<Entity> {
+        app.world_mut()
+            .query::<(Entity, &LevelKey)>()
+            .iter(app.world())
+            .find(|(_, LevelKey(entity_key))| entity_key == key)
+            .map(|(entity, _)| entity)
+    }
+
+    #[test]
+    fn leaving_and_returning_keeps_the_world_as_it_was() {
+        let mut app = streaming_app();
+        wait_for_world(&mut app, &WorldId::OUTSIDE);
+
+        // a prop knocked across the room, a damaged enemy, a dead one, a bullet in flight and
+        // loot on the ground
+        let cube_transform =
+            Transform::from_xyz(1.5, 0.25, -3.0).with_rotation(Quat::from_rotation_y(0.7));
+        let (linear, angular) = (Vec3::new(2.0, 0.0, -1.0), Vec3::new(0.0, 3.0, 0.0));
+        let cube = keyed(&mut app, "Dynamic Cube").expect("the cube is named");
+        app.world_mut().entity_mut(cube).insert((
+            cube_transform,
+            LinearVelocity(linear),
+            AngularVelocity(angular),
+        ));
+        let enemy_transform = Transform::from_xyz(-2.0, 0.25, 1.0);
+        let enemy = keyed(&mut app, "Enemy").expect("the enemy is named");
+        app.world_mut()
+            .entity_mut(enemy)
+            .insert((enemy_transform, Health(1)));
+        let chaser = keyed(&mut app, "Chaser").expect("the chaser is named");
+        app.world_mut().despawn(chaser);
+        let bullet_transform = Transform::from_xyz(0.0, 0.5, 4.0);
+        let bullet = Bullet {
+            direction: Dir3::X,
+            current_speed: 12.0,
+        };
+        app.world_mut()
+            .spawn((bullet_transform, bullet, WorldId::OUTSIDE));
+        let pickup_transform = Transform::from_xyz(-1.0, 0.1, 3.5);
+        app.world_mut()
+            .run_system_once(
+                move |mut commands: Commands,
+                      pickup_assets: Res<PickupAssets>,
+                      mut materials: ResMut<Assets<StandardMaterial>>| {
+                    spawn_pickup(
+                        &mut commands,
+                        &pickup_assets,
+                        &mut materials,
+                        pickup_transform,
+                        "Stool",
+                        0.5,
+                        WorldId::OUTSIDE,
+                    );
+                },
+            )
+            .unwrap();
+
+        switch_to(&mut app, WorldId::HUT);
+        assert!(keyed(&mut app, "Dynamic Cube").is_none());
+        assert!(keyed(&mut app, "Hut Exit").is_some());
+        switch_to(&mut app, WorldId::OUTSIDE);
+
+        let cube = keyed(&mut app, "Dynamic Cube").expect("the cube came back");
+        let world = app.world();
+        assert_eq!(world.get::<Transform>(cube), Some(&cube_transform));
+        assert_eq!(world.get::<LinearVelocity>(cube).map(|v| v.0), Some(linear));
+        assert_eq!(
+            world.get::<AngularVelocity>(cube).map(|v| v.0),
+            Some(angular)
+        );
+
+        let enemy = keyed(&mut app, "Enemy").expect("the enemy came back");
+        let world = app.world();
+        assert_eq!(world.get::<Transform>(enemy), Some(&enemy_transform));
+        assert_eq!(world.get::<Health>(enemy).map(|health| health.0), Some(1));
+
+        assert!(keyed(&mut app, "Chaser").is_none());
+        let outside = &app.world().resource::<WorldStreaming>().saved[&WorldId::OUTSIDE];
+        assert!(outside.removed.contains("Chaser"));
+
+        let bullets: Vec<_> = app
+            .world_mut()
+            .query::<(&Transform, &Bullet)>()
+            .iter(app.world())
+            .map(|(transform, bullet)| (*transform, bullet.current_speed))
+            .collect();
+        assert_eq!(bullets, vec![(bullet_transform, 12.0)]);
+
+        let pickups: Vec<_> = app
+            .world_mut()
+            .query::<(&Transform, &Pickup)>()
+            .iter(app.world())
+            .map(|(transform, pickup)| (*transform, pickup.item.clone()))
+            .collect();
+        assert_eq!(pickups, vec![(pickup_transform, "Stool".to_owned())]);
+    }
+}
@@ -1,231 +1,149 @@
-use avian3d::prelude::*;
 use bevy::prelude::*;
 
 use crate::{
     components::{
         gamestate::{ActivityState, LocationState},
         player::Player,
+        world::WorldId,
     },
     plugins::{
-        cutscene::CutsceneAction,
-        cutscene::CutsceneSequence,
+        cutscene::InterruptPolicy,
+        cutscene_script::{CutsceneScripts, PlayCutscene},
         input::KeyBindings,
-        level::{InsideWorld, OutsideWorld},
     },
 };
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 pub struct WorldSwitchingPlugin;
 
 impl Plugin for WorldSwitchingPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Update, check_for_retract);
-        app.init_resource::<OutsideCheckpoint>();
-        app.add_observer(on_moved_outside);
-        app.add_observer(on_moved_inside);
-        app.add_systems(
-            OnEnter(LocationState::Inside),
-            (
-                show_inside,
-                hide_outside,
-                enable_physics_inside,
-                disable_physics_outside,
-            ),
-        );
-        app.add_systems(
-            OnEnter(LocationState::Outside),
-            (
-                show_outside,
-                hide_inside,
-                enable_physics_outside,
-                disable_physics_inside,
-            ),
-        );
-        app.add_systems(
-            PostStartup,
-            (
-                show_outside,
-                hide_inside,
-                enable_physics_outside,
-                disable_physics_inside,
-            ),
-        );
+        app.init_resource::<ReturnCheckpoints>();
+        app.add_observer(on_use_door);
+        app.add_observer(on_return_to_checkpoint);
+        app.init_resource::<WorldRegistry>();
     }
 }
 
+pub struct WorldDefinition {
+    pub name: String,
+    // path of the level asset streamed in when the world is entered
+    pub level: String,
+    // whether the player can fire their gun here
+    pub combat: bool,
+}
+
 #[derive(Resource, Default)]
-struct OutsideCheckpoint {
-    transform: Option<Transform>,
+pub struct WorldRegistry {
+    worlds: HashMap<WorldId, WorldDefinition>,
 }
 
-fn check_for_retract(
-    mut commands: Commands,
-    keys: Res<ButtonInput<KeyCode>>,
-    keybinds: Res<KeyBindings>,
-) {
-    if keys.just_released((*keybinds).retract_to_shell) {
-        commands.trigger(MovedInside);
+impl WorldRegistry {
+    pub fn register(&mut self, id: WorldId, definition: WorldDefinition) {
+        self.worlds.insert(id, definition);
+    }
+
+    pub fn get(&self, id: &WorldId) -> Option<&WorldDefinition> {
+        self.worlds.get(id)
     }
 }
 
-#[derive(Event)]
-struct MovedInside;
+// fade and zoom into the player, switch to `world`, move to the `spawn` point there and zoom back out
+const DOOR_TRANSITION: &str = "door_transition";
+// same as the door transition but moves the player to a `target` position
+const CHECKPOINT_TRANSITION: &str = "checkpoint_transition";
 
-fn on_moved_inside(
-    _event: On<MovedInside>,
-    current_location: Res<State<LocationState>>,
-    current_activity: Res<State<ActivityState>>,
-    mut next_activity: ResMut<NextState<ActivityState>>,
-    mut outside_checkpoint: ResMut<OutsideCheckpoint>,
-    mut cutscene: ResMut<CutsceneSequence>,
-    player: Single<&mut Transform, With<Player>>,
-) {
-    if *current_location == LocationState::Outside && *current_activity == ActivityState::Playing {
-        outside_checkpoint.transform = Some(**player);
-        cutscene.add_actions(
-            &mut *next_activity,
-            vec![
-                CutsceneAction::FadeAndZoom {
-                    amount: 8.,
-                    duration: 0.4,
-                    start: None,
-                    reversed: false,
-                },
-                CutsceneAction::NextLevel(LocationState::Inside),
-                CutsceneAction::MovePlayer(Vec3::from([0.0, 0.0, 5.0])),
-                CutsceneAction::MoveCameraToPlayer,
-                CutsceneAction::FadeAndZoom {
-                    amount: 8.,
-                    duration: 0.4,
-                    start: None,
-                    reversed: true,
-                },
-                CutsceneAction::Wait(0.2),
-            ],
-        );
-    }
+// the spawn point in the shell the retract key leads to
+const SHELL_ENTRANCE: &str = "entrance";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub world: WorldId,
+    pub position: Vec3,
 }
 
-#[derive(Event)]
-pub struct MovedOutside;
+// where to go back to when leaving a world, one per door walked through
+#[derive(Resource, Default)]
+pub struct ReturnCheckpoints(pub Vec<Checkpoint>);
 
-fn on_moved_outside(
-    _event: On<MovedOutside>,
+fn check_for_retract(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    keybinds: Res<KeyBindings>,
     current_location: Res<State<LocationState>>,
-    mut outside_checkpoint: ResMut<OutsideCheckpoint>,
-    current_activity: Res<State<ActivityState>>,
-    mut next_activity: ResMut<NextState<ActivityState>>,
-    mut cutscene: ResMut<CutsceneSequence>,
+    player: Single<&Transform, With<Player>>,
 ) {
-    if *current_location == LocationState::Inside && *current_activity == ActivityState::Playing {
-        let target = outside_checkpoint.transform.take().unwrap().translation;
-        cutscene.add_actions(
-            &mut *next_activity,
-            vec![
-                CutsceneAction::FadeAndZoom {
-                    amount: 8.,
-                    duration: 0.4,
-                    start: None,
-                    reversed: false,
-                },
-                CutsceneAction::NextLevel(LocationState::Outside),
-                CutsceneAction::MovePlayer(target),
-                CutsceneAction::MoveCameraToPlayer,
-                CutsceneAction::FadeAndZoom {
-                    amount: 8.,
-                    duration: 0.4,
-                    start: None,
-                    reversed: true,
-                },
-                CutsceneAction::Wait(0.2),
-            ],
-        );
+    if keys.just_released((*keybinds).retract_to_shell) && current_location.0 != WorldId::SHELL {
+        commands.trigger(UseDoor {
+            world: WorldId::SHELL,
+            spawn: SHELL_ENTRANCE.into(),
+            return_to: player.translation,
+        });
     }
 }
 
-fn show_outside(
-    mut commands: Commands,
-    query: Query<Entity, (With<OutsideWorld>, With<Visibility>)>,
-) {
-    for entity in query {
-        commands.entity(entity).insert(Visibility::Visible);
-    }
+// moves the player to the `spawn` point of `world` and remembers `return_to` in the current one
+#[derive(Event)]
+pub struct UseDoor {
+    pub world: WorldId,
+    pub spawn: String,
+    pub return_to: Vec3,
 }
-fn hide_outside(
-    mut commands: Commands,
-    query: Query<Entity, (With<OutsideWorld>, With<Visibility>)>,
+
+fn on_use_door(
+    event: On<UseDoor>,
+    current_location: Res<State<LocationState>>,
+    current_activity: Res<State<ActivityState>>,
+    mut cutscenes: CutsceneScripts,
+    mut checkpoints: ResMut<ReturnCheckpoints>,
+    registry: Res<WorldRegistry>,
 ) {
-    for entity in query {
-        commands.entity(entity).insert(Visibility::Hidden);
+    // doors report a collision for every contact, only the first one goes through
+    if *current_activity != ActivityState::Playing || cutscenes.sequence.is_busy() {
+        return;
     }
-}
-fn show_inside(
-    mut commands: Commands,
-    query: Query<Entity, (With<InsideWorld>, With<Visibility>)>,
-) {
-    for entity in query {
-        commands.entity(entity).insert(Visibility::Visible);
+    if registry.get(&event.world).is_none() {
+        warn!("door leads to unregistered world {}", event.world);
+        return;
     }
-}
-fn hide_inside(
-    mut commands: Commands,
-    query: Query<Entity, (With<InsideWorld>, With<Visibility>)>,
-) {
-    for entity in query {
-        commands.entity(entity).insert(Visibility::Hidden);
+    let transition = PlayCutscene::new(DOOR_TRANSITION)
+        .with_param("world", event.world.clone())
+        .with_param("spawn", event.spawn.clone())
+        .with_policy(InterruptPolicy::IgnoreIfRunning);
+    // a transition that doesn't play never leaves the world, so there's nothing to return to
+    if cutscenes.play(&transition).is_some() {
+        checkpoints.0.push(Checkpoint {
+            world: current_location.0.clone(),
+            position: event.return_to,
+        });
     }
 }
 
-fn enable_physics_outside(
-    mut commands: Commands,
-    query: Query<
-        Entity,
-        (
-            With<OutsideWorld>,
-            With<RigidBodyDisabled>,
-            With<ColliderDisabled>,
-        ),
-    >,
-) {
-    for entity in query {
-        commands
-            .entity(entity)
-            .remove::<(RigidBodyDisabled, ColliderDisabled)>();
-    }
-}
-fn disable_physics_outside(
-    mut commands: Commands,
-    query: Query<Entity, (With<OutsideWorld>, With<RigidBody>, With<Collider>)>,
-) {
-    for entity in query {
-        commands
-            .entity(entity)
-            .insert((RigidBodyDisabled, ColliderDisabled));
-    }
-}
-fn enable_physics_inside(
-    mut commands: Commands,
-    query: Query<
-        Entity,
-        (
-            With<InsideWorld>,
-            With<RigidBodyDisabled>,
-            With<ColliderDisabled>,
-        ),
-    >,
+// goes back out through the last door
+#[derive(Event)]
+pub struct ReturnToCheckpoint;
+
+fn on_return_to_checkpoint(
+    _event: On<ReturnToCheckpoint>,
+    mut checkpoints: ResMut<ReturnCheckpoints>,
+    current_activity: Res<State<ActivityState>>,
+    mut cutscenes: CutsceneScripts,
 ) {
-    for entity in query {
-        commands
-            .entity(entity)
-            .remove::<(RigidBodyDisabled, ColliderDisabled)>();
+    if *current_activity != ActivityState::Playing || cutscenes.sequence.is_busy() {
+        return;
     }
-}
-fn disable_physics_inside(
-    mut commands: Commands,
-    query: Query<Entity, (With<InsideWorld>, With<RigidBody>, With<Collider>)>,
-) {
-    for entity in query {
-        commands
-            .entity(entity)
-            .insert((RigidBodyDisabled, ColliderDisabled));
+    let Some(checkpoint) = checkpoints.0.last() else {
+        warn!("left a world without a checkpoint to return to");
+        return;
+    };
+    let transition = PlayCutscene::new(CHECKPOINT_TRANSITION)
+        .with_param("world", checkpoint.world.clone())
+        .with_param("target", checkpoint.position)
+        .with_policy(InterruptPolicy::IgnoreIfRunning);
+    // kept for another try if the transition doesn't play
+    if cutscenes.play(&transition).is_some() {
+        checkpoints.0.pop();
     }
 }
@@ -0,0 +1,61 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
+use serde::de::DeserializeOwned;
+use std::{fmt, marker::PhantomData};
+
+// assets authored as plain RON files, loaded from files ending in EXTENSION
+pub trait RonAsset: Asset + DeserializeOwned {
+    const EXTENSION: &'static str;
+}
+
+#[derive(TypePath)]
+pub struct RonAssetLoader<A: RonAsset>(PhantomData<A>);
+
+impl<A: RonAsset> Default for RonAssetLoader<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+#[derive(Debug)]
+pub enum RonAssetError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl fmt::Display for RonAssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RonAssetError::Io(err) => write!(f, "could not read asset: {err}"),
+            RonAssetError::Ron(err) => write!(f, "could not parse asset: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RonAssetError {}
+
+impl<A: RonAsset> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonAssetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(RonAssetError::Io)?;
+        ron::de::from_bytes(&bytes).map_err(RonAssetError::Ron)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        std::slice::from_ref(&A::EXTENSION)
+    }
+}
@@ -0,0 +1,162 @@
+use ron::{Map, Value};
+use std::fmt;
+
+// upgrades the fields of a save by one version
+pub type Migration = fn(&mut Map) -> Result<(), String>;
+
+// MIGRATIONS[n] upgrades a version n + 1 save to version n + 2, append one for every change to
+// SaveFile so older saves keep loading, and check a save of the new version into tests/saves
+pub const MIGRATIONS: &[Migration] = &[add_pickups];
+
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32 + 1;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    NotAStruct,
+    UnknownVersion(u32),
+    Failed { from: u32, reason: String },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotAStruct => f.write_str("save file is not a struct"),
+            MigrationError::UnknownVersion(version) => write!(
+                f,
+                "save file has version {version}, this build reads versions 1 to {CURRENT_VERSION}"
+            ),
+            MigrationError::Failed { from, reason } => write!(
+                f,
+                "could not upgrade save file from version {from}: {reason}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+// version 2 keeps the loot lying on the ground of every world
+fn add_pickups(fields: &mut Map) -> Result<(), String> {
+    let Some(Value::Map(worlds)) = fields.get_mut(&Value::from("worlds")) else {
+        return Err("no worlds".into());
+    };
+    for world in worlds.values_mut() {
+        let Value::Map(world) = world else {
+            return Err("a world is not a struct".into());
+        };
+        world.insert(String::from("pickups"), Value::Seq(Vec::new()));
+    }
+    Ok(())
+}
+
+// runs every migration from `version` up to CURRENT_VERSION, one version at a time
+pub fn migrate(save: Value, version: u32) -> Result<Value, MigrationError> {
+    migrate_with(MIGRATIONS, save, version)
+}
+
+fn migrate_with(
+    migrations: &[Migration],
+    mut save: Value,
+    version: u32,
+) -> Result<Value, MigrationError> {
+    let current = migrations.len() as u32 + 1;
+    if version == 0 || version > current {
+        return Err(MigrationError::UnknownVersion(version));
+    }
+    let Value::Map(fields) = &mut save else {
+        return Err(MigrationError::NotAStruct);
+    };
+    for from in version..current {
+        migrations[from as usize - 1](fields)
+            .map_err(|reason| MigrationError::Failed { from, reason })?;
+        fields.insert(String::from("version"), from + 1);
+    }
+    Ok(save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a stand in for the first real migration, version 2 renames the flags
+    fn rename_flags(fields: &mut Map) -> Result<(), String> {
+        let flags = fields
+            .remove(&Value::from("flags"))
+            .ok_or("no flags to rename")?;
+        fields.insert(String::from("cutscene_flags"), flags);
+        Ok(())
+    }
+
+    fn sample_save(version: u32) -> Value {
+        ron::from_str(&format!(
+            r#"(version: {version}, flags: ["met_hermit"], location: ("shell"))"#
+        ))
+        .unwrap()
+    }
+
+    fn field<'a>(save: &'a Value, name: &str) -> Option<&'a Value> {
+        let Value::Map(fields) = save else {
+            panic!("save is not a struct");
+        };
+        fields.get(&Value::from(name))
+    }
+
+    #[test]
+    fn runs_each_step_and_bumps_the_version() {
+        let migrated = migrate_with(&[rename_flags], sample_save(1), 1).unwrap();
+        assert_eq!(field(&migrated, "version"), Some(&Value::from(2u32)));
+        assert!(field(&migrated, "flags").is_none());
+        assert_eq!(
+            field(&migrated, "cutscene_flags"),
+            field(&sample_save(1), "flags")
+        );
+        // untouched fields come through as they were
+        assert_eq!(
+            field(&migrated, "location"),
+            field(&sample_save(1), "location")
+        );
+    }
+
+    #[test]
+    fn version_2_adds_pickups_to_every_world() {
+        let save = ron::from_str(
+            r#"(version: 1, worlds: {"outside": (bullets: []), "shell": (bullets: [])})"#,
+        )
+        .unwrap();
+        let migrated = migrate(save, 1).unwrap();
+        let Some(Value::Map(worlds)) = field(&migrated, "worlds") else {
+            panic!("worlds is not a map");
+        };
+        assert_eq!(worlds.len(), 2);
+        for world in worlds.values() {
+            assert_eq!(field(world, "pickups"), Some(&Value::Seq(Vec::new())));
+        }
+    }
+
+    #[test]
+    fn current_saves_are_left_alone() {
+        let migrated = migrate_with(&[rename_flags], sample_save(2), 2).unwrap();
+        assert_eq!(migrated, sample_save(2));
+    }
+
+    #[test]
+    fn rejects_unknown_versions_and_failed_steps() {
+        assert!(matches!(
+            migrate_with(&[rename_flags], sample_save(3), 3),
+            Err(MigrationError::UnknownVersion(3))
+        ));
+        assert!(matches!(
+            migrate_with(&[rename_flags], sample_save(0), 0),
+            Err(MigrationError::UnknownVersion(0))
+        ));
+        let migrated = migrate_with(&[rename_flags, rename_flags], sample_save(1), 1);
+        assert!(matches!(
+            migrated,
+            Err(MigrationError::Failed { from: 2, .. })
+        ));
+        assert!(matches!(
+            migrate_with(&[rename_flags], Value::from(1u32), 1),
+            Err(MigrationError::NotAStruct)
+        ));
+    }
+}